@@ -0,0 +1,148 @@
+//! Benchmark results, as recorded by the `bench` binary and read back by `tables`.
+
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+/// Everything recorded by one invocation of `bench`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Results {
+    pub environment: Environment,
+    pub runs: Vec<Run>,
+}
+
+impl Results {
+    /// Reads results written by `write`.
+    pub fn read(path: &str) -> Result<Self, String> {
+        let file = ::std::fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        serde_json::from_reader(::std::io::BufReader::new(file)).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Writes the results as JSON.
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let file = ::std::fs::File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        serde_json::to_writer_pretty(::std::io::BufWriter::new(file), self).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+/// The machine and toolchain the results were recorded with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Environment {
+    /// Host name, as reported by the kernel.
+    pub host: String,
+    /// CPU model name.
+    pub cpu: String,
+    /// Number of logical cores.
+    pub cores: usize,
+    /// Kernel release.
+    pub os: String,
+    /// Output of `rustc --version`.
+    pub rustc: String,
+    /// Seconds since the Unix epoch when recording started.
+    pub timestamp: u64,
+}
+
+impl Environment {
+    /// Describes the current machine, with `unknown` for anything that cannot be determined.
+    pub fn detect() -> Self {
+        let read = |path: &str| ::std::fs::read_to_string(path).map(|text| text.trim().to_owned()).unwrap_or_else(|_| "unknown".to_owned());
+        let cpuinfo = ::std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+        let cpu = cpuinfo.lines()
+            .find(|line| line.starts_with("model name"))
+            .and_then(|line| line.splitn(2, ':').nth(1))
+            .map(|name| name.trim().to_owned())
+            .unwrap_or_else(|| "unknown".to_owned());
+        let cores = cpuinfo.lines().filter(|line| line.starts_with("processor")).count();
+        let rustc = Command::new("rustc").arg("--version").output().ok()
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|version| version.trim().to_owned())
+            .unwrap_or_else(|| "unknown".to_owned());
+        let timestamp = ::std::time::SystemTime::now()
+            .duration_since(::std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        Environment {
+            host: read("/proc/sys/kernel/hostname"),
+            cpu,
+            cores,
+            os: read("/proc/sys/kernel/osrelease"),
+            rustc,
+            timestamp,
+        }
+    }
+}
+
+/// One execution of one problem.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Run {
+    /// Problem name, as `doop`, `galen`, or `crdt`.
+    pub problem: String,
+    /// Directory of input facts.
+    pub input: String,
+    /// Number of timely workers.
+    pub workers: usize,
+    /// Which repetition of this configuration, from zero.
+    pub repetition: usize,
+    /// Whether the process exited successfully.
+    pub success: bool,
+    /// Seconds until inputs were loaded, as reported by the program.
+    pub load: Option<f64>,
+    /// Seconds until the initial fixpoint, as reported by the program.
+    pub fixpoint: Option<f64>,
+    /// Seconds taken by each subsequent update, in order.
+    pub updates: Vec<f64>,
+    /// Seconds from launch to exit, as measured by `bench`.
+    pub wall: f64,
+    /// Peak resident set size in kilobytes, if it could be observed.
+    pub peak_rss_kb: Option<u64>,
+    /// Final count of each reported output.
+    pub outputs: BTreeMap<String, i64>,
+}
+
+/// Parses a duration printed with `{:?}`, such as `1.5s`, `20.1ms`, `3µs` or `7ns`, into seconds.
+pub fn parse_duration(text: &str) -> Option<f64> {
+    let units = [("ns", 1e-9), ("µs", 1e-6), ("us", 1e-6), ("ms", 1e-3), ("s", 1.0)];
+    for &(unit, scale) in units.iter() {
+        if text.ends_with(unit) {
+            return text[.. text.len() - unit.len()].parse::<f64>().ok().map(|value| value * scale);
+        }
+    }
+    None
+}
+
+/// A duration in fractional seconds.
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn durations() {
+        let cases = [("1.5s", 1.5), ("20.1ms", 0.0201), ("3µs", 3e-6), ("3us", 3e-6), ("7ns", 7e-9), ("0ns", 0.0)];
+        for &(text, expected) in cases.iter() {
+            let parsed = parse_duration(text).unwrap_or_else(|| panic!("failed to parse {}", text));
+            assert!((parsed - expected).abs() <= expected * 1e-12, "{} parsed as {}", text, parsed);
+        }
+    }
+
+    #[test]
+    fn durations_match_debug() {
+        for &duration in [Duration::from_millis(1500), Duration::from_micros(250), Duration::from_nanos(7)].iter() {
+            let parsed = parse_duration(&format!("{:?}", duration)).expect("failed to parse");
+            assert!((parsed - seconds(duration)).abs() < 1e-12, "{:?} parsed as {}", duration, parsed);
+        }
+    }
+
+    #[test]
+    fn not_durations() {
+        for text in ["", "s", "1.5", "1.5 s", "fast", "inputs loaded"].iter() {
+            assert_eq!(parse_duration(text), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Runs problem binaries across worker counts and repetitions, recording results as JSON.
+//!
+//! Usage: `bench [--workers 1,2,4] [--repetitions 3] [--output results.json] "doop=<facts> 1" galen=<facts> crdt=<trace>`
+//!
+//! Each `name=path` runs the binary `name` built alongside `bench` on the inputs at `path`,
+//! followed by any further whitespace-separated arguments (doop needs its batch size here,
+//! as it must precede the worker count).
+//!
+//! Programs report progress as lines of `{:?}`-formatted elapsed time, a tab, and an event
+//! (`inputs loaded`, `computation initialized`, `complete`, `round N complete`, `demand updated`),
+//! and report output sizes as consolidated counts such as `Reach: ((), 0, 1234)`.
+
+extern crate differential;
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use differential::bench::{Environment, Results, Run, parse_duration, seconds};
+
+fn main() {
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut workers = vec![1];
+    let mut repetitions = 1;
+    let mut output = "bench.json".to_owned();
+    while let Some(position) = args.iter().position(|arg| arg == "--workers" || arg == "--repetitions" || arg == "--output") {
+        let flag = args.remove(position);
+        assert!(position < args.len(), "{} requires an argument", flag);
+        let value = args.remove(position);
+        match flag.as_str() {
+            "--workers" => workers = value.split(',').map(|w| w.parse().expect("malformed worker count")).collect(),
+            "--repetitions" => repetitions = value.parse().expect("malformed repetition count"),
+            _ => output = value,
+        }
+    }
+    let problems = args.iter().map(|arg| {
+        let mut parts = arg.splitn(2, '=');
+        let name = parts.next().unwrap().to_owned();
+        let rest = parts.next().unwrap_or_else(|| panic!("expected problem=path, found {}", arg));
+        let mut words = rest.split_whitespace().map(|word| word.to_owned());
+        let input = words.next().unwrap_or_else(|| panic!("missing path for {}", name));
+        (name, input, words.collect::<Vec<_>>())
+    }).collect::<Vec<_>>();
+    assert!(!problems.is_empty(), "no problems to run; supply problem=path arguments");
+
+    let mut results = Results { environment: Environment::detect(), runs: Vec::new() };
+
+    for (problem, input, extra) in problems.iter() {
+        for &count in workers.iter() {
+            for repetition in 0 .. repetitions {
+                let run = execute(problem, input, extra, count, repetition);
+                println!("{}\t{} workers\trepetition {}\tfixpoint {:?}\tpeak {:?}kB{}",
+                    problem, count, repetition, run.fixpoint, run.peak_rss_kb,
+                    if run.success { "" } else { "\tFAILED" });
+                results.runs.push(run);
+                // Record as we go, so an interrupted session keeps its completed runs.
+                results.write(&output).expect("failed to write results");
+            }
+        }
+    }
+}
+
+/// Runs `problem` once on `input` with `workers` workers, and collects what it reports.
+fn execute(problem: &str, input: &str, extra: &[String], workers: usize, repetition: usize) -> Run {
+
+    let binary = std::env::current_exe().expect("cannot locate bench").with_file_name(problem);
+    let timer = Instant::now();
+    let mut child =
+    Command::new(&binary)
+        .arg(input)
+        .args(extra)
+        .arg("-w")
+        .arg(workers.to_string())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| panic!("failed to start {}: {}", binary.display(), e));
+
+    // The kernel maintains the high-water mark, which we read until the process closes its output.
+    // Samples only ever raise the peak, as a process that has exited reports no mark at all.
+    let running = Arc::new(AtomicBool::new(true));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let poller = {
+        let (running, peak, pid) = (running.clone(), peak.clone(), child.id());
+        std::thread::spawn(move || {
+            let mut highest = 0;
+            while running.load(Ordering::SeqCst) {
+                if let Some(kb) = high_water_mark(pid) {
+                    highest = highest.max(kb);
+                    peak.store(highest, Ordering::SeqCst);
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        })
+    };
+
+    let mut load: Option<f64> = None;
+    let mut fixpoint: Option<f64> = None;
+    let mut marks = Vec::new();
+    let mut outputs = BTreeMap::new();
+
+    let stdout = BufReader::new(child.stdout.take().expect("stdout not captured"));
+    for line in stdout.lines() {
+        let line = line.expect("failed to read program output");
+        let mut fields = line.splitn(2, '\t');
+        let first = fields.next().unwrap_or("");
+        match (parse_duration(first), fields.next()) {
+            (Some(elapsed), Some(event)) => {
+                // Programs may report from every worker; the last worker to get there counts.
+                if event == "inputs loaded" {
+                    load = Some(load.map_or(elapsed, |l| l.max(elapsed)));
+                }
+                else if event.starts_with("round") || event == "demand updated" {
+                    marks.push(elapsed);
+                }
+                else if event == "computation initialized" || event == "computation initalized" || event == "complete" {
+                    fixpoint = Some(fixpoint.map_or(elapsed, |f| f.max(elapsed)));
+                }
+            },
+            _ => {
+                if let Some((name, diff)) = parse_count(&line) {
+                    *outputs.entry(name).or_insert(0) += diff;
+                }
+            },
+        }
+    }
+
+    // Stop polling before reaping the child, after which its pid may name another process.
+    running.store(false, Ordering::SeqCst);
+    poller.join().expect("poller panicked");
+    let status = child.wait().expect("failed to wait for program");
+    let wall = seconds(timer.elapsed());
+
+    // Update marks are cumulative; each update took the time since the one before.
+    let mut updates = Vec::new();
+    let mut previous = fixpoint.unwrap_or(0.0);
+    for mark in marks {
+        updates.push(mark - previous);
+        previous = mark;
+    }
+
+    let peak = peak.load(Ordering::SeqCst);
+    Run {
+        problem: problem.to_owned(),
+        input: input.to_owned(),
+        workers,
+        repetition,
+        success: status.success(),
+        load,
+        fixpoint,
+        updates,
+        wall,
+        peak_rss_kb: if peak > 0 { Some(peak as u64) } else { None },
+        outputs,
+    }
+}
+
+/// Reads `VmHWM` for `pid`, in kilobytes.
+fn high_water_mark(pid: u32) -> Option<usize> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line["VmHWM:".len() ..].trim().trim_end_matches("kB").trim().parse().ok()
+}
+
+/// Parses a consolidated count line such as `Reach: ((), 0, 1234)` into its name and change.
+fn parse_count(line: &str) -> Option<(String, i64)> {
+    let mut parts = line.splitn(2, ": ((), ");
+    let name = parts.next()?;
+    let rest = parts.next()?.trim_end_matches(')');
+    let diff = rest.rsplit(", ").next()?.parse().ok()?;
+    if name.chars().all(|c| c.is_alphanumeric() || c == '_') { Some((name.to_owned(), diff)) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn counts() {
+        assert_eq!(parse_count("Reach: ((), 0, 1234)"), Some(("Reach".to_owned(), 1234)));
+        assert_eq!(parse_count("result: ((), 3, -2)"), Some(("result".to_owned(), -2)));
+        assert_eq!(parse_count("VarPT: ((), (0, 1), 24564378)"), Some(("VarPT".to_owned(), 24564378)));
+    }
+
+    #[test]
+    fn not_counts() {
+        assert_eq!(parse_count("p: (1, 2)"), None);
+        assert_eq!(parse_count("Reach: ((), 0, many)"), None);
+        assert_eq!(parse_count("1.5s\tcomplete"), None);
+        assert_eq!(parse_count("two words: ((), 0, 1)"), None);
+    }
+}
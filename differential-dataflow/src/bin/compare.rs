@@ -0,0 +1,153 @@
+//! Compares the output relations of two engines.
+//!
+//! Usage: `compare [--samples 5] <ours> <theirs>`
+//!
+//! Each directory holds one `.csv` file per relation, named for the relation. Files may be
+//! tab- or comma-separated, with or without quoted fields, in any order; tuples are compared
+//! as sets after normalization. Relations are matched by name, ignoring case. The exit status
+//! is non-zero if any relation differs or is missing from either side.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+fn main() {
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut samples = 5;
+    if let Some(position) = args.iter().position(|arg| arg == "--samples") {
+        args.remove(position);
+        assert!(position < args.len(), "--samples requires an argument");
+        samples = args.remove(position).parse().expect("malformed sample count");
+    }
+    assert!(args.len() == 2, "expected two directories of relations");
+
+    let ours = relations(&args[0]);
+    let theirs = relations(&args[1]);
+
+    let names = ours.keys().chain(theirs.keys()).cloned().collect::<BTreeSet<_>>();
+    let mut differences = 0;
+    for name in names.iter() {
+        match (ours.get(name), theirs.get(name)) {
+            (Some((label, left)), Some((_, right))) => {
+                let missing = left.difference(right).collect::<Vec<_>>();
+                let extra = right.difference(left).collect::<Vec<_>>();
+                if missing.is_empty() && extra.is_empty() {
+                    println!("{}\tagree\t{} tuples", label, left.len());
+                }
+                else {
+                    differences += 1;
+                    println!("{}\tdiffer\t{} tuples ours, {} theirs; {} only ours, {} only theirs", label, left.len(), right.len(), missing.len(), extra.len());
+                    for tuple in missing.iter().take(samples) { println!("\t< {}", tuple.join(", ")); }
+                    for tuple in extra.iter().take(samples) { println!("\t> {}", tuple.join(", ")); }
+                }
+            },
+            (Some((label, left)), None) => {
+                differences += 1;
+                println!("{}\tmissing from {}\t{} tuples ours", label, args[1], left.len());
+            },
+            (None, Some((label, right))) => {
+                differences += 1;
+                println!("{}\tmissing from {}\t{} tuples theirs", label, args[0], right.len());
+            },
+            (None, None) => unreachable!(),
+        }
+    }
+
+    println!("{} of {} relations differ", differences, names.len());
+    if differences > 0 { std::process::exit(1); }
+}
+
+/// The relations in `directory`, keyed by lowercase name, with their file name and tuples.
+fn relations(directory: &str) -> BTreeMap<String, (String, BTreeSet<Vec<String>>)> {
+    let mut relations = BTreeMap::new();
+    let entries = std::fs::read_dir(directory).unwrap_or_else(|e| panic!("{}: {}", directory, e));
+    for entry in entries {
+        let path = entry.unwrap_or_else(|e| panic!("{}: {}", directory, e)).path();
+        if path.extension().map_or(false, |extension| extension == "csv") {
+            let name = path.file_stem().expect("csv without name").to_string_lossy().into_owned();
+            if let Some((previous, _)) = relations.insert(name.to_lowercase(), (name.clone(), tuples(&path))) {
+                panic!("{}: both {} and {} name one relation", directory, previous, name);
+            }
+        }
+    }
+    relations
+}
+
+/// The distinct tuples in the file at `path`.
+///
+/// Lines containing tabs are split on tabs only, as fields such as method signatures may
+/// contain commas; other lines are split on commas. Fields are trimmed, and a field opening
+/// with a double quote runs to its closing quote, so quoted fields may contain the delimiter.
+fn tuples(path: &Path) -> BTreeSet<Vec<String>> {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| fields(line, if line.contains('\t') { '\t' } else { ',' }))
+        .collect()
+}
+
+/// Splits `line` at each `delimiter` outside double quotes, trimming and unquoting fields.
+///
+/// Within quotes a doubled quote stands for one quote character.
+fn fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c != '"' { field.push(c); }
+            else if chars.peek() == Some(&'"') { chars.next(); field.push('"'); }
+            else { quoted = false; }
+        }
+        else if c == '"' && field.trim().is_empty() {
+            field.clear();
+            quoted = true;
+        }
+        else if c == delimiter {
+            fields.push(field.trim().to_owned());
+            field.clear();
+        }
+        else {
+            field.push(c);
+        }
+    }
+    fields.push(field.trim().to_owned());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn quoted_delimiters() {
+        assert_eq!(fields("a, \"b, c\" ,d", ','), vec!["a", "b, c", "d"]);
+        assert_eq!(fields("\"x\ty\"\tz", '\t'), vec!["x\ty", "z"]);
+        assert_eq!(fields("<A: void f(int,int)>\t1", '\t'), vec!["<A: void f(int,int)>", "1"]);
+    }
+
+    #[test]
+    fn doubled_quotes() {
+        assert_eq!(fields("\"say \"\"hi\"\"\",2", ','), vec!["say \"hi\"", "2"]);
+        assert_eq!(fields("\"\"\"\"", ','), vec!["\""]);
+        assert_eq!(fields("\"\",", ','), vec!["", ""]);
+        // A quote after other text is part of the field.
+        assert_eq!(fields("a\"b,c", ','), vec!["a\"b", "c"]);
+    }
+
+    #[test]
+    fn mixed_delimiters() {
+        let path = std::env::temp_dir().join(format!("compare-mixed-{}.csv", std::process::id()));
+        std::fs::write(&path, "1\t<A: void f(int,int)>\n2,b\n\n3\t\"c\"\n").unwrap();
+        let read = tuples(&path);
+        std::fs::remove_file(&path).unwrap();
+        let expected: BTreeSet<Vec<String>> = vec![
+            vec!["1".to_owned(), "<A: void f(int,int)>".to_owned()],
+            vec!["2".to_owned(), "b".to_owned()],
+            vec!["3".to_owned(), "c".to_owned()],
+        ].into_iter().collect();
+        assert_eq!(read, expected);
+    }
+}
@@ -1,26 +1,59 @@
 extern crate timely;
 // extern crate graph_map;
 extern crate differential_dataflow;
+extern crate differential;
 
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::io::{BufRead, BufReader};
 use std::fs::File;
+use std::path::Path;
+use std::rc::Rc;
 
 use differential_dataflow::input::Input;
 use differential_dataflow::operators::*;
 // use differential_dataflow::operators::arrange::ArrangeByKey;
 use differential_dataflow::Collection;
-use timely::dataflow::ProbeHandle;
+use timely::dataflow::{ProbeHandle, Scope};
+use timely::dataflow::operators::{Exchange, Inspect, Probe};
 
-type Id = (usize, usize);
+use differential::rga::{Document, Id};
+use differential::shell::write_relation;
 
 fn main() {
 
-    timely::execute_from_args(std::env::args(), move |worker| {
+    // `--check batch` replays the trace in batches of `batch` inserts, with a proportionate share
+    // of removes, and compares the visible order after each batch with the document order that
+    // `differential::rga` computes. The process fails if any batch disagrees.
+    // `--faithful` evaluates `problems/crdt/query.dl` rule by rule, rather than as optimized here.
+    // `--output dir` writes `result.csv` to `dir` once the trace is applied.
+    let mut args: Vec<String> = std::env::args().collect();
+    let faithful = args.iter().any(|arg| arg == "--faithful");
+    args.retain(|arg| arg != "--faithful");
+    let mut check = None;
+    if let Some(position) = args.iter().position(|arg| arg == "--check") {
+        args.remove(position);
+        assert!(position < args.len(), "--check requires a batch size");
+        check = Some(args.remove(position).parse::<usize>().expect("malformed batch size"));
+    }
+    let mut output = None;
+    if let Some(position) = args.iter().position(|arg| arg == "--output") {
+        args.remove(position);
+        assert!(position < args.len(), "--output requires a directory");
+        output = Some(args.remove(position));
+    }
+    assert!(check != Some(0), "--check requires a positive batch size");
+
+    let guards = timely::execute_from_args(args.clone().into_iter(), move |worker| {
 
         let timer = ::std::time::Instant::now();
         let peers = worker.peers();
         let index = worker.index();
         let mut probe = ProbeHandle::new();
+        // Accumulated `next_visible` pairs, gathered at worker 0 when checking.
+        let observed = Rc::new(RefCell::new(HashMap::new()));
+        // Accumulated `result` tuples, gathered at worker 0 when writing outputs.
+        let results = Rc::new(RefCell::new(HashMap::new()));
 
         let (mut insert, mut remove, mut assign) = worker.dataflow::<usize,_,_>(|scope| {
 
@@ -29,128 +62,370 @@ fn main() {
             let (r_handle, remove) = scope.new_collection::<Id,isize>();
             let (a_handle, assign) = scope.new_collection::<(Id, Id, String),isize>();
 
-            let has_child = insert.map(|(_, x)| x).distinct_total();
+            let (next_visible, current_value) =
+            if faithful { faithful_rules(&insert, &remove, &assign) }
+            else { optimized_rules(&insert, &remove, &assign) };
 
-            let insert_by_parent = insert.map(|(x,y)| (y,x));
+            let result =
+            next_visible
+                .map(|(prev,next)| (next,prev))
+                .join_map(&current_value, |next, prev, value| (prev.0, next.0, value.clone()));
+
+            result.probe_with(&mut probe);
+            result.map(|_| ()).consolidate().inspect(|x| println!("result: {:?}", x));
+
+            if output.is_some() {
+                let results = results.clone();
+                result
+                    .inner
+                    .exchange(|_| 0)
+                    .inspect(move |(tuple, _time, diff)| *results.borrow_mut().entry(tuple.clone()).or_insert(0) += diff)
+                    .probe_with(&mut probe);
+            }
+
+            if check.is_some() {
+                let observed = observed.clone();
+                next_visible
+                    .inner
+                    .exchange(|_| 0)
+                    .inspect(move |&(pair, _time, diff)| *observed.borrow_mut().entry(pair).or_insert(0) += diff)
+                    .probe_with(&mut probe);
+            }
 
-            let siblings =
-            insert_by_parent
-                .reduce(move |_key, input, output| {
-                    for i in 1..input.len() {
-                        output.push(((*input[i].0, *input[i-1].0), 1));
+            (i_handle, r_handle, a_handle)
+        });
+
+        let path = args.get(1).expect("missing arg: trace path");
+        let inserts = load_inserts(&format!("{}/insert.txt", path));
+        let removes = load_removes(&format!("{}/remove.txt", path));
+
+        let agreed = match check {
+            None => {
+                for (count, &(id, parent)) in inserts.iter().enumerate() {
+                    if count % peers == index {
+                        insert.insert((id, parent));
+                        assign.insert((id, id, "".to_string()));
+                    }
+                }
+                for (count, &id) in removes.iter().enumerate() {
+                    if count % peers == index {
+                        remove.insert(id);
+                    }
+                }
+
+                println!("{:?}\tinputs loaded", timer.elapsed());
+
+                insert.close();
+                remove.close();
+                assign.close();
+                // println!("{:?}", timer.elapsed());
+
+                while worker.step() {
+                }
+                println!("{:?}\tcomplete", timer.elapsed());
+                true
+            },
+            Some(batch) => {
+                let batches = ((inserts.len() + batch - 1) / batch).max(1);
+                let share = (removes.len() + batches - 1) / batches;
+                let mut document = Document::new();
+                let mut failures = 0;
+
+                for round in 0 .. batches {
+
+                    let inserted = &inserts[(round * batch).min(inserts.len()) .. ((round + 1) * batch).min(inserts.len())];
+                    let removed = &removes[(round * share).min(removes.len()) .. ((round + 1) * share).min(removes.len())];
+                    for (count, &(id, parent)) in inserted.iter().enumerate() {
+                        if count % peers == index {
+                            insert.insert((id, parent));
+                            assign.insert((id, id, "".to_string()));
+                        }
+                        document.insert(id, parent);
+                    }
+                    for (count, &id) in removed.iter().enumerate() {
+                        if count % peers == index {
+                            remove.insert(id);
+                        }
+                        document.remove(id);
                     }
-                });
 
-            let next_sibling = siblings.map(|(_parent, pair)| pair);
+                    insert.advance_to(round + 1); insert.flush();
+                    remove.advance_to(round + 1); remove.flush();
+                    assign.advance_to(round + 1); assign.flush();
+                    while probe.less_than(insert.time()) {
+                        worker.step();
+                    }
 
-            let first_child =
-            siblings
-                .map(|(parent, (_child1, child2))| (parent, child2))
-                .negate()
-                .concat(&insert_by_parent);
+                    if index == 0 {
+                        let expected = document.pairs().into_iter().collect::<HashSet<_>>();
+                        let observed = observed.borrow();
+                        let mut missing = expected.iter().filter(|pair| observed.get(*pair) != Some(&1)).collect::<Vec<_>>();
+                        let mut extra = observed.iter().filter(|&(pair, &diff)| diff != 0 && (diff != 1 || !expected.contains(pair))).collect::<Vec<_>>();
+                        println!("{:?}\tround {} complete", timer.elapsed(), round);
+                        if !missing.is_empty() || !extra.is_empty() {
+                            failures += 1;
+                            missing.sort();
+                            extra.sort();
+                            println!("batch {}: {} expected pairs, {} missing, {} unexpected", round, expected.len(), missing.len(), extra.len());
+                            for pair in missing.iter().take(5) { println!("\tmissing: {:?}", pair); }
+                            for (pair, diff) in extra.iter().take(5) { println!("\tunexpected: {:?} with multiplicity {}", pair, diff); }
+                        }
+                    }
+                }
+
+                if index == 0 {
+                    println!("{:?}\tcheck complete: {} of {} batches agree", timer.elapsed(), batches - failures, batches);
+                }
+                failures == 0
+            },
+        };
+
+        if let (0, Some(directory)) = (index, output.as_ref()) {
+            let results = results.borrow();
+            let mut rows = results.iter().filter(|&(_, &diff)| diff > 0).map(|(tuple, _)| tuple).collect::<Vec<_>>();
+            rows.sort();
+            let rows = rows.into_iter().map(|&(ctr1, ctr2, ref value)| vec![ctr1.to_string(), ctr2.to_string(), value.clone()]);
+            std::fs::create_dir_all(directory).expect("failed to create output directory");
+            write_relation(&Path::new(directory).join("result.csv"), rows).expect("failed to write outputs");
+        }
+        agreed
+    }).unwrap();
 
-            let has_next_sibling: Collection<_,Id,_> = next_sibling.map(|(x,_)| x).distinct_total();
+    if guards.join().into_iter().any(|result| result != Ok(true)) {
+        std::process::exit(1);
+    }
+}
 
-            // NB: Done using `iterate` because no mutual recursion.
-            let next_sibling_anc = next_sibling.iterate(|inner| {
+/// The `(prev, next)` pairs of adjacent visible elements, and the current value of each element.
+///
+/// Siblings are ordered by a `reduce` rather than by comparing all pairs, and first children
+/// are those without a preceding sibling, rather than the negation of a later-child relation.
+fn optimized_rules<G: Scope<Timestamp=usize>>(
+    insert: &Collection<G, (Id, Id)>,
+    remove: &Collection<G, Id>,
+    assign: &Collection<G, (Id, Id, String)>,
+) -> (Collection<G, (Id, Id)>, Collection<G, (Id, String)>) {
+
+    let has_child = insert.map(|(_, x)| x).distinct_total();
+
+    let insert_by_parent = insert.map(|(x,y)| (y,x));
+
+    let siblings =
+    insert_by_parent
+        .reduce(move |_key, input, output| {
+            for i in 1..input.len() {
+                output.push(((*input[i].0, *input[i-1].0), 1));
+            }
+        });
 
-                let insert = insert.enter(&inner.scope());
-                let has_next_sibling = has_next_sibling.enter(&inner.scope());
-                let next_sibling = next_sibling.enter(&inner.scope());
+    let next_sibling = siblings.map(|(_parent, pair)| pair);
 
-                insert.antijoin(&has_next_sibling)
-                      .map(|(start, parent)| (parent, start))
-                      .join_map(&inner, |_parent, &start, &next| (start, next))
-                      .concat(&next_sibling)
-                      .distinct()
+    let first_child =
+    siblings
+        .map(|(parent, (_child1, child2))| (parent, child2))
+        .negate()
+        .concat(&insert_by_parent);
 
-            });
+    let has_next_sibling: Collection<_,Id,_> = next_sibling.map(|(x,_)| x).distinct_total();
 
-            // next_sibling_anc.map(|_|()).consolidate().inspect(|x| println!("nsa: {:?}", x.2));
+    // NB: Done using `iterate` because no mutual recursion.
+    let next_sibling_anc = next_sibling.iterate(|inner| {
 
-            let next_elem = first_child.concat(&next_sibling_anc.antijoin(&has_child));
+        let insert = insert.enter(&inner.scope());
+        let has_next_sibling = has_next_sibling.enter(&inner.scope());
+        let next_sibling = next_sibling.enter(&inner.scope());
 
-            let current_value =
-            assign
-                .map(|(id, elem, value)| (id, (elem, value)))
-                .antijoin(&remove)
-                .map(|(_, (elem, value))| (elem, value));
+        insert.antijoin(&has_next_sibling)
+              .map(|(start, parent)| (parent, start))
+              .join_map(&inner, |_parent, &start, &next| (start, next))
+              .concat(&next_sibling)
+              .distinct()
 
-            let has_value = current_value.map(|(elem,_)| elem).distinct_total();
+    });
 
-            let value_step = next_elem.semijoin(&has_value);            // (from,to) where has_value(from).
-            let blank_step = value_step.negate().concat(&next_elem);    // (from,to) where !has_value(from).
+    // next_sibling_anc.map(|_|()).consolidate().inspect(|x| println!("nsa: {:?}", x.2));
 
-            // Paths of the form value (blank)^*.
-            let value_blank_star = value_step.iterate(|inner| {
+    let next_elem = first_child.concat(&next_sibling_anc.antijoin(&has_child));
 
-                let value_step = value_step.enter(&inner.scope());
-                let blank_step = blank_step.enter(&inner.scope());
+    let current_value =
+    assign
+        .map(|(id, elem, value)| (id, (elem, value)))
+        .antijoin(remove)
+        .map(|(_, (elem, value))| (elem, value));
 
-                inner.map(|(from, via)| (via, from))
-                     .join_map(&blank_step, |_via, &from, &to| (from, to))
-                     .concat(&value_step)
-                     .distinct()
-            });
+    let has_value = current_value.map(|(elem,_)| elem).distinct_total();
 
-            // value_blank_star.map(|_|()).consolidate().inspect(|x| println!("vbs: {:?}", x.2));
+    let value_step = next_elem.semijoin(&has_value);            // (from,to) where has_value(from).
+    let blank_step = value_step.negate().concat(&next_elem);    // (from,to) where !has_value(from).
 
-            // Paths of the form value (blank)^* value.
-            let next_visible =
-            value_blank_star
-                .map(|(prev, next)| (next,prev))
-                .semijoin(&has_value)
-                .map(|(next,prev)| (prev,next));
+    // Paths of the form value (blank)^*.
+    let value_blank_star = value_step.iterate(|inner| {
 
-            let result =
-            next_visible
-                .map(|(prev,next)| (next,prev))
-                .join_map(&current_value, |next, prev, value| (prev.0, next.0, value.clone()));
+        let value_step = value_step.enter(&inner.scope());
+        let blank_step = blank_step.enter(&inner.scope());
 
-            result.probe_with(&mut probe);
-            result.map(|_| ()).consolidate().inspect(|x| println!("result: {:?}", x));
+        inner.map(|(from, via)| (via, from))
+             .join_map(&blank_step, |_via, &from, &to| (from, to))
+             .concat(&value_step)
+             .distinct()
+    });
 
-            (i_handle, r_handle, a_handle)
-        });
+    // value_blank_star.map(|_|()).consolidate().inspect(|x| println!("vbs: {:?}", x.2));
 
-        let mut args = std::env::args().skip(1);
-        let path = args.next().expect("missing arg: trace path");
-
-        let filename = format!("{}/insert.txt", path);
-        let file = BufReader::new(File::open(filename).unwrap());
-        for (count, readline) in file.lines().enumerate() {
-            if count % peers == index {
-                let line = readline.ok().expect("read error");
-                let mut elts = line[..].split_whitespace();
-                let id_ctr:   usize = elts.next().unwrap().parse().ok().expect("malformed id_ctr");
-                let id_node:  usize = elts.next().unwrap().parse().ok().expect("malformed id_node");
-                let ref_ctr:  usize = elts.next().unwrap().parse().ok().expect("malformed ref_ctr");
-                let ref_node: usize = elts.next().unwrap().parse().ok().expect("malformed ref_node");
-                insert.insert(((id_ctr, id_node), (ref_ctr, ref_node)));
-                assign.insert(((id_ctr, id_node), (id_ctr, id_node), "".to_string()));
-            }
-        }
+    // Paths of the form value (blank)^* value.
+    let next_visible =
+    value_blank_star
+        .map(|(prev, next)| (next,prev))
+        .semijoin(&has_value)
+        .map(|(next,prev)| (prev,next));
 
-        let filename = format!("{}/remove.txt", path);
-        let file = BufReader::new(File::open(filename).unwrap());
-        for (count, readline) in file.lines().enumerate() {
-            if count % peers == index {
-                let line = readline.ok().expect("read error");
-                let mut elts = line[..].split_whitespace();
-                let ref_ctr:  usize = elts.next().unwrap().parse().ok().expect("malformed ref_ctr");
-                let ref_node: usize = elts.next().unwrap().parse().ok().expect("malformed ref_node");
-                remove.insert((ref_ctr, ref_node));
-            }
-        }
+    (next_visible, current_value)
+}
 
-        insert.close();
-        remove.close();
-        assign.close();
-        // println!("{:?}", timer.elapsed());
+/// As `optimized_rules`, but following `problems/crdt/query.dl` rule by rule.
+///
+/// Each relation is a set, as in Datalog, so any rule whose projection may produce duplicates
+/// is followed by `distinct`. This includes the sibling relations, which are quadratic in the
+/// number of children of a parent, and `laterSibling2`, which is cubic.
+fn faithful_rules<G: Scope<Timestamp=usize>>(
+    insert: &Collection<G, (Id, Id)>,
+    remove: &Collection<G, Id>,
+    assign: &Collection<G, (Id, Id, String)>,
+) -> (Collection<G, (Id, Id)>, Collection<G, (Id, String)>) {
+
+    let insert_by_parent = insert.map(|(child, parent)| (parent, child));
+
+    // hasChild(Parent) :- insert(_, Parent).
+    let has_child = insert.map(|(_, parent)| parent).distinct();
+
+    // laterChild(Parent, [Ctr2, N2]) :-
+    //   insert([Ctr1, N1], Parent),
+    //   insert([Ctr2, N2], Parent),
+    //   (Ctr1 > Ctr2; (Ctr1 = Ctr2, N1 > N2)).
+    let later_child =
+    insert_by_parent
+        .join(&insert_by_parent)
+        .filter(|&(_parent, (child1, child2))| child1 > child2)
+        .map(|(parent, (_child1, child2))| (parent, child2))
+        .distinct();
+
+    // firstChild(Parent, Child) :- insert(Child, Parent), !laterChild(Parent, Child).
+    let first_child =
+    insert_by_parent
+        .map(|pair| (pair, ()))
+        .antijoin(&later_child)
+        .map(|(pair, ())| pair);
+
+    // sibling(Child1, Child2) :- insert(Child1, Parent), insert(Child2, Parent).
+    let sibling =
+    insert_by_parent
+        .join_map(&insert_by_parent, |_parent, &child1, &child2| (child1, child2))
+        .distinct();
+
+    // laterSibling([Ctr1,N1], [Ctr2,N2]) :-
+    //   sibling([Ctr1,N1], [Ctr2,N2]),
+    //   (Ctr1 > Ctr2; (Ctr1 = Ctr2, N1 > N2)).
+    let later_sibling = sibling.filter(|&(sib1, sib2)| sib1 > sib2);
+
+    // laterSibling2([Ctr1,N1], [Ctr3,N3]) :-
+    //   sibling([Ctr1,N1], [Ctr2,N2]),
+    //   sibling([Ctr1,N1], [Ctr3,N3]),
+    //   (Ctr1 > Ctr2; (Ctr1 = Ctr2, N1 > N2)),
+    //   (Ctr2 > Ctr3; (Ctr2 = Ctr3, N2 > N3)).
+    let later_sibling2 =
+    later_sibling
+        .join(&sibling)
+        .filter(|&(_sib1, (sib2, sib3))| sib2 > sib3)
+        .map(|(sib1, (_sib2, sib3))| (sib1, sib3))
+        .distinct();
+
+    // nextSibling(Sib1, Sib2) :- laterSibling(Sib1, Sib2), !laterSibling2(Sib1, Sib2).
+    let next_sibling =
+    later_sibling
+        .map(|pair| (pair, ()))
+        .antijoin(&later_sibling2)
+        .map(|(pair, ())| pair);
+
+    // hasNextSibling(Sib1) :- laterSibling(Sib1, _).
+    let has_next_sibling = later_sibling.map(|(sib1, _)| sib1).distinct();
+
+    // nextSiblingAnc(Start, Next) :- nextSibling(Start, Next).
+    // nextSiblingAnc(Start, Next) :- !hasNextSibling(Start), insert(Start, Parent), nextSiblingAnc(Parent, Next).
+    let next_sibling_anc = next_sibling.iterate(|inner| {
+
+        let insert = insert.enter(&inner.scope());
+        let has_next_sibling = has_next_sibling.enter(&inner.scope());
+        let next_sibling = next_sibling.enter(&inner.scope());
+
+        insert.antijoin(&has_next_sibling)
+              .map(|(start, parent)| (parent, start))
+              .join_map(&inner, |_parent, &start, &next| (start, next))
+              .concat(&next_sibling)
+              .distinct()
+    });
+
+    // nextElem(Prev, Next) :- firstChild(Prev, Next).
+    // nextElem(Prev, Next) :- !hasChild(Prev), nextSiblingAnc(Prev, Next).
+    let next_elem =
+    first_child
+        .concat(&next_sibling_anc.antijoin(&has_child))
+        .distinct();
+
+    // currentValue(Elem, Value) :- assign(ID, Elem, Value), !remove(ID).
+    let current_value =
+    assign
+        .map(|(id, elem, value)| (id, (elem, value)))
+        .antijoin(remove)
+        .map(|(_, (elem, value))| (elem, value))
+        .distinct();
+
+    // hasValue(Elem) :- currentValue(Elem, _).
+    let has_value = current_value.map(|(elem, _)| elem).distinct();
+
+    // skipBlank(From, To) :- nextElem(From, To).
+    // skipBlank(From, To) :- skipBlank(Via, To), nextElem(From, Via), !hasValue(Via).
+    let skip_blank = next_elem.iterate(|inner| {
+
+        let next_elem = next_elem.enter(&inner.scope());
+        let has_value = has_value.enter(&inner.scope());
+
+        inner.antijoin(&has_value)
+             .join_map(&next_elem.map(|(from, via)| (via, from)), |_via, &to, &from| (from, to))
+             .concat(&next_elem)
+             .distinct()
+    });
+
+    // nextVisible(Prev, Next) :- hasValue(Prev), skipBlank(Prev, Next), hasValue(Next).
+    let next_visible =
+    skip_blank
+        .semijoin(&has_value)
+        .map(|(prev, next)| (next, prev))
+        .semijoin(&has_value)
+        .map(|(next, prev)| (prev, next));
+
+    (next_visible, current_value)
+}
 
-        while worker.step() {
-        }
-        println!("{:?}\tcomplete", timer.elapsed());
+fn load_inserts(filename: &str) -> Vec<(Id, Id)> {
+    let file = BufReader::new(File::open(filename).unwrap());
+    file.lines().map(|readline| {
+        let line = readline.ok().expect("read error");
+        let mut elts = line[..].split_whitespace();
+        let id_ctr:   usize = elts.next().unwrap().parse().ok().expect("malformed id_ctr");
+        let id_node:  usize = elts.next().unwrap().parse().ok().expect("malformed id_node");
+        let ref_ctr:  usize = elts.next().unwrap().parse().ok().expect("malformed ref_ctr");
+        let ref_node: usize = elts.next().unwrap().parse().ok().expect("malformed ref_node");
+        ((id_ctr, id_node), (ref_ctr, ref_node))
+    }).collect()
+}
 
-    }).unwrap();
+fn load_removes(filename: &str) -> Vec<Id> {
+    let file = BufReader::new(File::open(filename).unwrap());
+    file.lines().map(|readline| {
+        let line = readline.ok().expect("read error");
+        let mut elts = line[..].split_whitespace();
+        let ref_ctr:  usize = elts.next().unwrap().parse().ok().expect("malformed ref_ctr");
+        let ref_node: usize = elts.next().unwrap().parse().ok().expect("malformed ref_node");
+        (ref_ctr, ref_node)
+    }).collect()
 }
@@ -2,11 +2,11 @@
 
 extern crate timely;
 extern crate differential_dataflow;
+extern crate differential;
 
-
-use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::path::Path;
 
 use timely::dataflow::{Scope, ProbeHandle};
 use timely::dataflow::scopes::child::Iterative as Child;
@@ -19,6 +19,10 @@ use differential_dataflow::operators::iterate::Variable;
 use differential_dataflow::operators::{Threshold, Join, JoinCore, Consolidate};
 use differential_dataflow::operators::arrange::ArrangeByKey;
 
+use differential::StringInterner;
+use differential::shell::Shell;
+use differential::report::{Labels, Memory, Operators};
+
 // Type aliases for differential execution.
 type Time = u32;
 type Iter = u32;
@@ -99,33 +103,6 @@ where G::Timestamp : Lattice {
     }
 }
 
-struct StringInterner {
-    vec: Vec<String>,
-    map: HashMap<String, Symbol>
-}
-
-impl StringInterner {
-    pub fn new() -> Self { StringInterner { vec: Vec::new(), map: HashMap::new() } }
-    // pub fn intern(&mut self, string: &str) -> Symbol {
-    //     string.to_owned()
-    // }
-    pub fn intern(&mut self, string: &str) -> Symbol {
-        if !self.map.contains_key(string) {
-            let len = self.map.len() as Symbol;
-            self.vec.push(string.to_owned());
-            self.map.insert(string.to_owned(), len);
-            len
-        }
-        else {
-            *self.map.get(string).unwrap()
-        }
-    }
-    pub fn concat(&mut self, id1: Symbol, id2: Symbol) -> Symbol {
-        let string = self.vec[id1 as usize].to_owned() + &self.vec[id2 as usize];
-        self.intern(&string)
-    }
-}
-
 fn read_file(filename: &str) -> impl Iterator<Item=String> {
     use ::std::io::{BufReader, BufRead};
     use ::std::fs::File;
@@ -256,11 +233,38 @@ fn load7<'a>(index: usize, prefix: &str, filename: &str, interner: Rc<RefCell<St
 
 fn main() {
 
-    let mut args = std::env::args().skip(1);
-    let prefix = args.next().expect("must supply path to facts");
-    let batch: Time = args.next().unwrap_or("1".to_string()).parse().expect("batch must be an integer");
+    // `--shell` keeps the computation alive after fixpoint, for interactive exploration.
+    // `--serve addr` does the same, serving requests on a TCP address or `unix:path` socket.
+    // `--memory` reports the size of each arrangement at fixpoint and after each round.
+    // `--profile` reports the time spent and records received by each rule at the end of the run.
+    // `--output dir` writes `Reachable.csv`, `VarPointsTo.csv`, and `CallGraphEdge.csv` to `dir` at fixpoint.
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut interactive = args.iter().any(|arg| arg == "--shell");
+    let report_memory = args.iter().any(|arg| arg == "--memory");
+    let profile = args.iter().any(|arg| arg == "--profile");
+    args.retain(|arg| arg != "--shell" && arg != "--memory" && arg != "--profile");
+    let mut serve = None;
+    let mut output = None;
+    while let Some(position) = args.iter().position(|arg| arg == "--serve" || arg == "--output") {
+        let flag = args.remove(position);
+        assert!(position < args.len(), "{} requires an argument", flag);
+        let value = args.remove(position);
+        match flag.as_str() {
+            "--serve" => { serve = Some(value); interactive = true; },
+            _ => output = Some(value),
+        }
+    }
+
+    // Most arrangements hold updates of a pair of symbols, at a time within an iteration.
+    let labels = Labels::new();
+    let operators = Operators::new();
+    let memory = Memory::new(labels.clone(), operators.clone(), ::std::mem::size_of::<((Symbol, Symbol), (Time, Iter), Diff)>());
+    let profiled = (labels.clone(), operators.clone());
+
+    let prefix = args.get(1).cloned().expect("must supply path to facts");
+    let batch: Time = args.get(2).cloned().unwrap_or("1".to_string()).parse().expect("batch must be an integer");
 
-    timely::execute_from_args(std::env::args(), move |worker| {
+    timely::execute_from_args(args.into_iter(), move |worker| {
 
         let timer = ::std::time::Instant::now();
         let index = worker.index();
@@ -269,6 +273,7 @@ fn main() {
 
         // For interning strings.
         let interner = Rc::new(RefCell::new(StringInterner::new()));
+        let mut shell = Shell::new(interner.clone(), 1);
 
         let mut inputs = (
             Vec::new(),
@@ -280,6 +285,9 @@ fn main() {
             Vec::new(),
         );
 
+        if report_memory || profile { operators.register(worker); }
+        if report_memory { memory.register(worker); }
+
         // let inputs =
         worker.dataflow::<Time,_,_>(|scope| {
 
@@ -347,6 +355,7 @@ fn main() {
             let temp2 = interner.borrow_mut().intern("<<main method array content>>");
             let temp3 = interner.borrow_mut().intern("<<main method array>>");
             let temp4 = interner.borrow_mut().intern("java.lang.String[]");
+            labels.label(scope, "HeapAllocation_Type");
             let HeapAllocation_Type =
             _NormalHeap
                 .concat(&_StringConstant.map(move |s| (s, temp1.clone())))
@@ -358,6 +367,7 @@ fn main() {
             // NOTE: Unused
             // let MainMethodArgArrayContent: Collection<_,HeapAllocation> = scope.new_collection_from_raw(Some(temp2.clone())).1;
 
+            labels.label(scope, "Instruction_Method");
             let Instruction_Method = //: Collection<_,(Instruction, Method)> =
             _AssignHeapAllocation.map(|x| (x.0, x.4))
                 .concat(&_AssignLocal.map(|x| (x.0, x.4)))
@@ -419,6 +429,7 @@ fn main() {
 
             // Fat schema
 
+            labels.label(scope, "LoadInstanceField");
             // LoadInstanceField(?base, ?sig, ?to, ?inmethod) :-
             //  Instruction_Method(?insn, ?inmethod),
             //  LoadInstanceField_Base(?insn, ?base),
@@ -431,6 +442,7 @@ fn main() {
                 .join(&LoadInstanceField_To)
                 .map(|(_insn, (((inmethod, base), sig), to))| (base, sig, to, inmethod));
 
+            labels.label(scope, "StoreInstanceField");
             // StoreInstanceField(?from, ?base, ?sig, ?inmethod) :-
             //  Instruction_Method(?insn, ?inmethod),
             //  StoreInstanceField_From(?insn, ?from),
@@ -443,6 +455,7 @@ fn main() {
                 .join(&FieldInstruction_Signature)
                 .map(|(_insn, (((inmethod, from), base), sig))| (from, base, sig, inmethod));
 
+            labels.label(scope, "LoadStaticField");
             // LoadStaticField(?sig, ?to, ?inmethod) :-
             //  Instruction_Method(?insn, ?inmethod),
             //  FieldInstruction_Signature(?insn, ?sig),
@@ -453,6 +466,7 @@ fn main() {
                 .join(&LoadStaticField_To)
                 .map(|(_insn, ((inmethod, sig), to))| (sig, to, inmethod));
 
+            labels.label(scope, "StoreStaticField");
             // StoreStaticField(?from, ?sig, ?inmethod) :-
             //  Instruction_Method(?insn, ?inmethod),
             //  StoreStaticField_From(?insn, ?from),
@@ -463,6 +477,7 @@ fn main() {
                 .join(&FieldInstruction_Signature)
                 .map(|(_insn, ((inmethod, from), sig))| (from, sig, inmethod));
 
+            labels.label(scope, "LoadArrayIndex");
             // LoadArrayIndex(?base, ?to, ?inmethod) :-
             //  Instruction_Method(?insn, ?inmethod),
             //  LoadArrayIndex_Base(?insn, ?base),
@@ -473,6 +488,7 @@ fn main() {
                 .join(&LoadArrayIndex_To)
                 .map(|(_insn, ((inmethod, base), to))| (base, to, inmethod));
 
+            labels.label(scope, "StoreArrayIndex");
             // StoreArrayIndex(?from, ?base, ?inmethod) :-
             //  Instruction_Method(?insn, ?inmethod),
             //  StoreArrayIndex_From(?insn, ?from),
@@ -483,6 +499,7 @@ fn main() {
                 .join(&StoreArrayIndex_Base)
                 .map(|(_insn, ((inmethod, from), base))| (from, base, inmethod));
 
+            labels.label(scope, "AssignCast");
             // AssignCast(?type, ?from, ?to, ?inmethod) :-
             //  Instruction_Method(?insn, ?inmethod),
             //  AssignCast_From(?insn, ?from),
@@ -495,6 +512,7 @@ fn main() {
                 .join(&AssignCast_Type)
                 .map(|(_insn, (((inmethod, from), to), ty))| (ty, from, to, inmethod));
 
+            labels.label(scope, "AssignLocal");
             // AssignLocal(?from, ?to, ?inmethod) :-
             //  AssignInstruction_To(?insn, ?to),
             //  Instruction_Method(?insn, ?inmethod),
@@ -505,6 +523,7 @@ fn main() {
                 .join(&AssignLocal_From)
                 .map(|(_insn, ((inmethod, to), from))| (from, to, inmethod));
 
+            labels.label(scope, "AssignHeapAllocation");
             // AssignHeapAllocation(?heap, ?to, ?inmethod) :-
             //  Instruction_Method(?insn, ?inmethod),
             //  AssignHeapAllocation_Heap(?insn, ?heap),
@@ -515,6 +534,7 @@ fn main() {
                 .join(&AssignInstruction_To)
                 .map(|(_insn, ((inmethod, heap), to))| (heap, to, inmethod));
 
+            labels.label(scope, "ReturnVar");
             // ReturnVar(?var, ?method) :-
             //  Instruction_Method(?insn, ?method),
             //  ReturnNonvoid_Var(?insn, ?var).
@@ -523,6 +543,7 @@ fn main() {
                 .join(&ReturnNonvoid_Var)
                 .map(|(_insn, (inmethod, var))| (var, inmethod));
 
+            labels.label(scope, "StaticMethodInvocation");
             // StaticMethodInvocation(?invocation, ?signature, ?inmethod) :-
             //  isStaticMethodInvocation_Insn(?invocation),
             //  Instruction_Method(?invocation, ?inmethod),
@@ -533,6 +554,7 @@ fn main() {
                 .join(&MethodInvocation_Method)
                 .map(|(invocation, (inmethod, sig))| (invocation, sig, inmethod));
 
+            labels.label(scope, "VirtualMethodInvocation_SimpleName");
             // VirtualMethodInvocation_SimpleName(?invocation, ?simplename),
             // VirtualMethodInvocation_Descriptor(?invocation, ?descriptor) :-
             //  isVirtualMethodInvocation_Insn(?invocation),
@@ -598,6 +620,7 @@ fn main() {
                 let mut Superinterface = Relation::<_,(Type, Type)>::new(scope);
                 let mut SubtypeOf = Relation::<_,(Type, Type)>::new(scope);
 
+                labels.label(scope, "MethodImplemented");
                 // MethodImplemented(?simplename, ?descriptor, ?type, ?method) :-
                 //  Method_SimpleName(?method, ?simplename),
                 //  Method_Descriptor(?method, ?descriptor),
@@ -613,6 +636,7 @@ fn main() {
                     .threshold(|_,_| 1 as Diff);
                     // .distinct();
 
+                labels.label(scope, "MethodLookup");
                 // MethodLookup(?simplename, ?descriptor, ?type, ?method) :-
                 //  MethodImplemented(?simplename, ?descriptor, ?type, ?method).
                 // MethodLookup(?simplename, ?descriptor, ?type, ?method) :-
@@ -633,18 +657,22 @@ fn main() {
                 // MethodImplemented.map(|_| ()).consolidate().inspect(|x| println!("MI: {:?}", x));
                 // MethodLookup.map(|_| ()).consolidate().inspect(|x| println!("ML: {:?}", x));
 
+                labels.label(scope, "DirectSubclass");
                 // DirectSubclass(?a, ?c) :- DirectSuperclass(?a, ?c).
                 let DirectSubclass = DirectSuperclass.clone();  // TODO: This seems badly named.
 
+                labels.label(scope, "Subclass");
                 // Subclass(?c, ?a) :- DirectSubclass(?a, ?c).
                 // Subclass(?c, ?a) :- Subclass(?b, ?a),DirectSubclass(?b, ?c).
                 let SubclassClone = Subclass.clone();
                 Subclass.add_production(&DirectSubclass.map(|x| (x.1, x.0)));
                 Subclass.add_production(&SubclassClone.join_map(&DirectSubclass, |_b, a, c| (c.clone(), a.clone())));
 
+                labels.label(scope, "Superclass");
                 // Superclass(?c, ?a) :- Subclass(?a, ?c).
                 let _Superclass = Subclass.map(|x| (x.1, x.0));
 
+                labels.label(scope, "Superinterface");
                 // Superinterface(?k, ?c) :- DirectSuperinterface(?c, ?k).
                 // Superinterface(?k, ?c) :- DirectSuperinterface(?c, ?j),Superinterface(?k, ?j).
                 // Superinterface(?k, ?c) :- DirectSuperclass(?c, ?super),Superinterface(?k, ?super).
@@ -663,26 +691,35 @@ fn main() {
                         .map(|(_j, (c, k))| (k, c))
                 );
 
+                labels.label(scope, "SupertypeOf");
                 // SupertypeOf(?s, ?t) :- SubtypeOf(?t, ?s).
                 let SupertypeOf = SubtypeOf.map(|x| (x.1, x.0));
 
                 let SubtypeOfClone = SubtypeOf.clone();
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?s) :- isClassType(?s).
                 SubtypeOf.add_production(&ClassType.map(|x| (x.clone(), x)));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?t) :- Subclass(?t, ?s).
                 SubtypeOf.add_production(&Subclass.map(|x| (x.1, x.0)));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?t) :- isClassType(?s),Superinterface(?t, ?s).
                 SubtypeOf.add_production(&Superinterface.map(|x| (x.1, x.0)).semijoin(&ClassType));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?t) :- isInterfaceType(?s),isType(?t),?t = "java.lang.Object".
                 let temp = interner.borrow_mut().intern("java.lang.Object");
                 SubtypeOf.add_production(&InterfaceType.map(move |x| (x, temp.clone())));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?s) :- isInterfaceType(?s).
                 SubtypeOf.add_production(&InterfaceType.map(|x| (x.clone(), x)));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?t) :- isInterfaceType(?s),Superinterface(?t, ?s).
                 SubtypeOf.add_production(&Superinterface.map(|x| (x.1, x.0)).semijoin(&InterfaceType));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?t) :- isArrayType(?s),isType(?t),?t = "java.lang.Object".
                 let temp = interner.borrow_mut().intern("java.lang.Object");
                 SubtypeOf.add_production(&ArrayType.map(move |x| (x, temp.clone())));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?t) :-
                 //  ComponentType(?s, ?sc),
                 //  ComponentType(?t, ?tc),
@@ -696,18 +733,23 @@ fn main() {
                         .semijoin(&isReferenceType)
                         .join_map(&ComponentType.map(|x| (x.1, x.0)), |_tc, s, t| (s.clone(), t.clone()))
                 );
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?t) :- isArrayType(?s),isInterfaceType(?t),isType(?t),?t = "java.lang.Cloneable".
                 let temp = interner.borrow_mut().intern("java.lang.Cloneable");
                 SubtypeOf.add_production(&ArrayType.map(move |x| (x, temp.clone())));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?s, ?t) :- isArrayType(?s),isInterfaceType(?t),isType(?t),?t = "java.io.Serializable".
                 let temp = interner.borrow_mut().intern("java.io.Serializable");
                 SubtypeOf.add_production(&ArrayType.map(move |x| (x, temp.clone())));
+                labels.label(scope, "SubtypeOf");
                 // SubtypeOf(?t, ?t) :- isType(?t).
                 SubtypeOf.add_production(&isType.map(|x| (x.clone(), x)));
 
+                labels.label(scope, "SubtypeOfDifferent");
                 // SubtypeOfDifferent(?s, ?t) :- SubtypeOf(?s, ?t),?s != ?t.
                 let _SubtypeOfDifferent = SubtypeOf.filter(|x| x.0 != x.1);
 
+                labels.label(scope, "MainMethodDeclaration");
                 // MainMethodDeclaration(?method) :-
                 //  MainClass(?type),
                 //  Method_DeclaringType(?method, ?type),
@@ -739,9 +781,13 @@ fn main() {
 
                 let result = (MethodImplemented.leave(), MainMethodDeclaration.leave(), MethodLookup.leave(), SupertypeOf.leave());
 
+                labels.label(scope, "MethodLookup (distinct)");
                 MethodLookup.complete();
+                labels.label(scope, "Subclass (distinct)");
                 Subclass.complete();
+                labels.label(scope, "Superinterface (distinct)");
                 Superinterface.complete();
+                labels.label(scope, "SubtypeOf (distinct)");
                 SubtypeOf.complete();
 
                 result
@@ -798,6 +844,7 @@ fn main() {
                 let mut Reachable = Relation::<_,(Method)>::new(scope);
 
                 // NOTE: Common subexpression.
+                labels.label(scope, "Reachable_Invocation");
                 let Reachable_Invocation =
                     Instruction_Method
                         .as_collection(|inv,meth| (meth.clone(), inv.clone()))
@@ -811,6 +858,7 @@ fn main() {
                 // Class initialization
                 let mut InitializedClass = Relation::<_,(Type)>::new(scope);
 
+                labels.label(scope, "ClassInitializer");
                 // ClassInitializer(?type, ?method) :- basic.MethodImplemented("<clinit>", "void()", ?type, ?method).
                 let temp1 = interner.borrow_mut().intern("<clinit>");
                 let temp2 = interner.borrow_mut().intern("void()");
@@ -821,12 +869,16 @@ fn main() {
                     .distinct();
 
                 let InitializedClassClone = InitializedClass.clone();
+                labels.label(scope, "InitializedClass");
                 // InitializedClass(?superclass) :- InitializedClass(?class),DirectSuperclass(?class, ?superclass).
                 InitializedClass.add_production(&DirectSuperclass.semijoin(&InitializedClassClone).map(|x| x.1));
+                labels.label(scope, "InitializedClass");
                 // InitializedClass(?superinterface) :- InitializedClass(?classOrInterface),DirectSuperinterface(?classOrInterface, ?superinterface).
                 InitializedClass.add_production(&DirectSuperinterface.semijoin(&InitializedClassClone).map(|x| x.1));
+                labels.label(scope, "InitializedClass");
                 // InitializedClass(?class) :- basic.MainMethodDeclaration(?method),Method_DeclaringType(?method, ?class).
                 InitializedClass.add_production(&Method_DeclaringType.semijoin(&MainMethodDeclaration).map(|x| x.1));
+                labels.label(scope, "InitializedClass");
                 // InitializedClass(?class) :-
                 //  Reachable(?inmethod),
                 //  AssignHeapAllocation(?heap, _, ?inmethod),
@@ -838,6 +890,7 @@ fn main() {
                         .map(|(_inmethod,heap)| (heap, ()))
                         .join_map(&HeapAllocation_Type, |_,(),class| class.clone())
                 );
+                labels.label(scope, "InitializedClass");
                 // InitializedClass(?class) :-
                 //  Reachable(?inmethod),
                 //  Instruction_Method(?invocation, ?inmethod),
@@ -851,6 +904,7 @@ fn main() {
                         .join_map(&Method_DeclaringType, |_,(),class| class.clone())
                 );
 
+                labels.label(scope, "InitializedClass");
                 // InitializedClass(?classOrInterface) :-
                 //  Reachable(?inmethod),
                 //  StoreStaticField(_, ?signature, ?inmethod),
@@ -862,6 +916,7 @@ fn main() {
                         .map(|(_meth,sig)| (sig, ()))
                         .join_map(&Field_DeclaringType, |_,(),class| class.clone())
                 );
+                labels.label(scope, "InitializedClass");
                 // InitializedClass(?classOrInterface) :-
                 //  Reachable(?inmethod),
                 //  LoadStaticField(?signature, _, ?inmethod),
@@ -874,6 +929,7 @@ fn main() {
                         .join_map(&Field_DeclaringType, |_,_,class| class.clone())
                 );
 
+                labels.label(scope, "Reachable");
                 // Reachable(?clinit) :- InitializedClass(?class),ClassInitializer(?class, ?clinit).
                 Reachable.add_production(&ClassInitializer.semijoin(&InitializedClass).map(|x| x.1));
 
@@ -882,8 +938,10 @@ fn main() {
                 let mut VarPointsTo = Relation::<_,(HeapAllocation, Var)>::new(scope);
                 let mut CallGraphEdge = Relation::<_,(MethodInvocation, Method)>::new(scope);
 
+                labels.label(scope, "VarPointsToRev");
                 let VarPointsToRev = VarPointsTo.map(|x| (x.1, x.0)).arrange_by_key();
 
+                labels.label(scope, "ArrayIndexPointsTo");
                 // ArrayIndexPointsTo(?baseheap, ?heap) :-
                 //  Reachable(?inmethod),
                 //  StoreArrayIndex(?from, ?base, ?inmethod),
@@ -909,6 +967,7 @@ fn main() {
                     .semijoin(&SupertypeOf)
                     .map(|(_,(bh,h))| (bh,h));
 
+                labels.label(scope, "Assign");
                 // Assign(?actual, ?formal) :-
                 //  CallGraphEdge(?invocation, ?method),
                 //  FormalParam(?index, ?method, ?formal),
@@ -921,6 +980,7 @@ fn main() {
                         .join(&ActualParam.map(|x| ((x.0, x.1), x.2)))
                         .map(|(_ind_inv, (formal, actual))| (actual, formal))
                 );
+                labels.label(scope, "Assign");
                 // Assign(?return, ?local) :-
                 //  CallGraphEdge(?invocation, ?method),
                 //  ReturnVar(?return, ?method),
@@ -933,6 +993,7 @@ fn main() {
                         .map(|(_meth, (local, ret))| (ret, local))
                 );
 
+                labels.label(scope, "InstanceFieldPointsTo");
                 // InstanceFieldPointsTo(?heap, ?fld, ?baseheap) :-
                 //  Reachable(?inmethod),
                 //  StoreInstanceField(?from, ?base, ?fld, ?inmethod),
@@ -949,6 +1010,7 @@ fn main() {
                 // SIMPLIFICATION: ALL CGE DERIVATIONS PRODUCE REACHABILITY.
                 Reachable.add_production(&CallGraphEdge.map(|x| x.1));
 
+                labels.label(scope, "CallGraphEdge");
                 // CallGraphEdge(?invocation, ?toMethod) :-
                 //  Reachable(?inMethod),
                 //  Instruction_Method(?invocation, ?inMethod),
@@ -972,6 +1034,7 @@ fn main() {
                         .map(|(_, (inv, to))| (inv, to))
                 );
 
+                labels.label(scope, "CallGraphEdge");
                 // CallGraphEdge(?invocation, ?tomethod) :-
                 //  Reachable(?inmethod),
                 //  StaticMethodInvocation(?invocation, ?tomethod, ?inmethod).
@@ -982,6 +1045,7 @@ fn main() {
                         .map(|(_inmethod, (inv, to))| (inv, to))
                 );
 
+                labels.label(scope, "CallGraphEdge");
                 // CallGraphEdge(?invocation, ?tomethod),
                 // VarPointsTo(?heap, ?this) :-
                 //  Reachable(?inmethod),
@@ -1002,9 +1066,11 @@ fn main() {
                 CallGraphEdge.add_production(&temp.map(|(i,t,_,_)| (i,t)));
                 VarPointsTo.add_production(&temp.map(|(_,_,h,t)| (h,t)));
 
+                labels.label(scope, "Reachable");
                 // Reachable(?method) :- basic.MainMethodDeclaration(?method).
                 Reachable.add_production(&MainMethodDeclaration);
 
+                labels.label(scope, "StaticFieldPointsTo");
                 // StaticFieldPointsTo(?heap, ?fld) :-
                 //  Reachable(?inmethod),
                 //  StoreStaticField(?from, ?fld, ?inmethod),
@@ -1017,6 +1083,7 @@ fn main() {
                     .join_core(&VarPointsToRev, |_from,fld,heap| Some((heap.clone(), fld.clone())));
 
                 let VarPointsToClone = VarPointsTo.clone();
+                labels.label(scope, "VarPointsTo");
                 // VarPointsTo(?heap, ?var) :-
                 //  AssignHeapAllocation(?heap, ?var, ?inMethod),
                 //  Reachable(?inMethod).
@@ -1027,6 +1094,7 @@ fn main() {
                         .map(|x| x.1)
                 );
 
+                labels.label(scope, "VarPointsTo");
                 // VarPointsTo(?heap, ?to) :- Assign(?from, ?to),VarPointsTo(?heap, ?from).
                 VarPointsTo.add_production(
                     &VarPointsToClone
@@ -1035,6 +1103,7 @@ fn main() {
                         .map(|(_from, (heap, to))| (heap, to))
                 );
 
+                labels.label(scope, "VarPointsTo");
                 // VarPointsTo(?heap, ?to) :-
                 //  Reachable(?inmethod),
                 //  AssignLocal(?from, ?to, ?inmethod),
@@ -1047,6 +1116,7 @@ fn main() {
                         .join_core(&VarPointsToRev, |_from,to,heap| Some((heap.clone(), to.clone())))
                 );
 
+                labels.label(scope, "VarPointsTo");
                 // VarPointsTo(?heap, ?to) :-
                 //  Reachable(?method),
                 //  AssignCast(?type, ?from, ?to, ?method),
@@ -1066,6 +1136,7 @@ fn main() {
                         .map(|((heap, _f), to)| (heap, to))
                 );
 
+                labels.label(scope, "VarPointsTo");
                 // VarPointsTo(?heap, ?to) :-
                 //  Reachable(?inmethod),
                 //  LoadArrayIndex(?base, ?to, ?inmethod),
@@ -1092,6 +1163,7 @@ fn main() {
                         .map(|(_, (to,h))| (h,to))
                 );
 
+                labels.label(scope, "VarPointsTo");
                 // VarPointsTo(?heap, ?to) :-
                 //  Reachable(?inmethod),
                 //  LoadInstanceField(?base, ?signature, ?to, ?inmethod),
@@ -1107,6 +1179,7 @@ fn main() {
                         .map(|(_, (t,h))| (h,t))
                 );
 
+                labels.label(scope, "VarPointsTo");
                 // VarPointsTo(?heap, ?to) :-
                 //  Reachable(?inmethod),
                 //  LoadStaticField(?fld, ?to, ?inmethod),
@@ -1120,6 +1193,7 @@ fn main() {
                         .map(|(_f,(t,h))| (h,t))
                 );
 
+                labels.label(scope, "VarPointsTo");
                 // VarPointsTo(?heap, ?this) :-
                 //  Reachable(?inMethod),
                 //  Instruction_Method(?invocation, ?inMethod),
@@ -1147,10 +1221,15 @@ fn main() {
 
                 let result = (SupertypeOf.leave(), InitializedClass.leave(), Reachable.leave(), VarPointsTo.leave(), CallGraphEdge.leave());
 
+                labels.label(scope, "Reachable (distinct)");
                 Reachable.complete();
+                labels.label(scope, "InitializedClass (distinct)");
                 InitializedClass.complete();
+                labels.label(scope, "Assign (distinct)");
                 Assign.complete();
+                labels.label(scope, "VarPointsTo (distinct)");
                 VarPointsTo.complete();
+                labels.label(scope, "CallGraphEdge (distinct)");
                 CallGraphEdge.complete();
 
                 result
@@ -1187,10 +1266,20 @@ fn main() {
                 .inspect(|x| println!("Graph: {:?}", x))
                 .probe_with(&mut probe);
 
+            if interactive || output.is_some() {
+                shell.output("Reachable", 1, &_reachable.map(|m| vec![m]), &mut probe);
+                shell.output("VarPointsTo", 2, &_varpoints.map(|(h,v)| vec![h,v]), &mut probe);
+                shell.output("CallGraphEdge", 2, &_callgraph.map(|(i,m)| vec![i,m]), &mut probe);
+            }
+
             // (input1, input2, input3, input4, input5, input6, input7, input8, input9, input10, input11, input12, input13, input14, input15, input16, input17, input18, input19, input20, input21, input22, input23, input24, input25, input26, input27, input28, input29, input30, input31)
 
         });
 
+        if worker.index() == 0 {
+            println!("{:?}\tinputs loaded", timer.elapsed());
+        }
+
         if worker.index() == 0 {
 
             for input in inputs.0.iter_mut() { input.advance_to(1); input.flush(); }
@@ -1204,8 +1293,36 @@ fn main() {
             while probe.less_than(inputs.0[0].time()) { worker.step(); }
 
             println!("{:?}\tcomputation initalized", timer.elapsed());
+            if report_memory { memory.report(); }
+            if let Some(directory) = output.as_ref() {
+                shell.write(Path::new(directory)).expect("failed to write outputs");
+            }
 
-            if batch > 0 {
+            if interactive {
+
+                // Names of the inputs, in the order they were pushed into `inputs` above.
+                let names1 = ["ClassType", "ArrayType", "InterfaceType", "ApplicationClass", "StringConstant", "MainClass"];
+                let names2 = ["ThisVar", "NormalHeap", "DirectSuperclass", "DirectSuperinterface", "Method_Modifier", "Var_Type", "ComponentType", "AssignReturnValue"];
+                let names3 = ["FormalParam", "ActualParam"];
+                let names4 = ["Field", "StaticMethodInvocation", "Return"];
+                let names5 = ["AssignLocal", "SpecialMethodInvocation", "VirtualMethodInvocation", "StoreStaticField", "LoadStaticField", "StoreArrayIndex", "LoadArrayIndex"];
+                let names6 = ["AssignHeapAllocation", "AssignCast", "StoreInstanceField", "LoadInstanceField"];
+                let names7 = ["Method"];
+
+                for (name, input) in names1.iter().zip(inputs.0.drain(..)) { shell.input(name, input); }
+                for (name, input) in names2.iter().zip(inputs.1.drain(..)) { shell.input(name, input); }
+                for (name, input) in names3.iter().zip(inputs.2.drain(..)) { shell.input(name, input); }
+                for (name, input) in names4.iter().zip(inputs.3.drain(..)) { shell.input(name, input); }
+                for (name, input) in names5.iter().zip(inputs.4.drain(..)) { shell.input(name, input); }
+                for (name, input) in names6.iter().zip(inputs.5.drain(..)) { shell.input(name, input); }
+                for (name, input) in names7.iter().zip(inputs.6.drain(..)) { shell.input(name, input); }
+
+                match serve.as_ref() {
+                    Some(address) => differential::server::serve(&mut shell, address, worker, &probe).expect("server failed"),
+                    None => shell.run(worker, &probe),
+                }
+            }
+            else if batch > 0 {
 
                 // Load methods from disk
                 let methods_stuff = load7(index, &prefix, "Method.facts", interner.clone()).collect::<Vec<_>>();
@@ -1228,6 +1345,7 @@ fn main() {
                     if round % batch == batch - 1 {
                         while probe.less_than(inputs.0[0].time()) { worker.step(); }
                         println!("{:?}\tround {} complete", timer.elapsed(), round);
+                        if report_memory { memory.report(); }
                     }
 
                 }
@@ -1235,4 +1353,7 @@ fn main() {
         }
 
     }).expect("Timely computation did not complete cleanly");
+
+    // Workers have finished and flushed their logs.
+    if profile { profiled.1.profile(&profiled.0); }
 }
@@ -1,12 +1,26 @@
 extern crate timely;
 extern crate differential_dataflow;
+extern crate differential;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
 
 use timely::order::Product;
 use timely::dataflow::*;
+use timely::dataflow::operators::{Exchange, Map};
 
+use differential_dataflow::{AsCollection, Collection};
+use differential_dataflow::ExchangeData as Data;
 use differential_dataflow::input::Input;
 use differential_dataflow::operators::*;
 
+use differential::shell::{Shell, Numbers};
+use differential::report::{Labels, Operators};
+
 type Node = u32;
 type Time = u32;
 type Iter = u32;
@@ -14,12 +28,72 @@ type Diff = isize;
 
 fn main() {
 
+    // `--explain p:x,z` or `--explain q:x,r,z` requests a derivation tree for a fact.
+    // `--why-not p:x,z` or `--why-not q:x,r,z` reports why a fact was not derived.
+    // `--demand x` computes only p(x,_) and q(x,_,_); further `+ x` / `- x` lines are read from stdin.
+    // `--shell` keeps the computation alive after fixpoint, for interactive exploration.
+    // `--serve addr` does the same, serving requests on a TCP address or `unix:path` socket.
+    // `--provenance` gathers what the shell's `explain` and `why-not` commands need, at worker zero.
+    // `--profile` reports the time spent and records received by each rule at the end of the run.
+    // `--plan IR4=pp` evaluates a three-way rule with another join order; see `PLANS`.
+    // `--sizes` reports the updates produced by each three-way rule's first join and by the rule.
+    // `--output dir` writes `p.csv` and `q.csv` to `dir` at fixpoint, for comparison with other engines.
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut interactive = args.iter().any(|arg| arg == "--shell");
+    let profile = args.iter().any(|arg| arg == "--profile");
+    let sizes = args.iter().any(|arg| arg == "--sizes");
+    let commands = args.iter().any(|arg| arg == "--provenance");
+    args.retain(|arg| arg != "--shell" && arg != "--profile" && arg != "--sizes" && arg != "--provenance");
+    let mut explain = Vec::new();
+    let mut why_not = Vec::new();
+    let mut demanded = Vec::new();
+    let mut serve = None;
+    let mut output = None;
+    let mut plans = PLANS.iter().map(|&(rule, plans)| (rule, plans[0])).collect::<HashMap<_,_>>();
+    while let Some(position) = args.iter().position(|arg| arg == "--explain" || arg == "--why-not" || arg == "--demand" || arg == "--serve" || arg == "--output" || arg == "--plan") {
+        let flag = args.remove(position);
+        assert!(position < args.len(), "{} requires an argument", flag);
+        let value = args.remove(position);
+        match flag.as_str() {
+            "--explain" => explain.push(Fact::parse(&value).expect("malformed fact")),
+            "--why-not" => why_not.push(Fact::parse(&value).expect("malformed fact")),
+            "--serve" => { serve = Some(value); interactive = true; },
+            "--output" => output = Some(value),
+            "--plan" => {
+                let mut parts = value.splitn(2, '=');
+                let (rule, plan) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+                let &(rule, options) = PLANS.iter().find(|&&(name, _)| name == rule).unwrap_or_else(|| panic!("no plans for {}; expected IR3, IR4, or IR6", rule));
+                let &plan = options.iter().find(|&&option| option == plan).unwrap_or_else(|| panic!("no plan {} for {}; expected one of {:?}", plan, rule, options));
+                plans.insert(rule, plan);
+            },
+            _ => demanded.push(value.parse::<Node>().expect("malformed node")),
+        }
+    }
+    // Gathering rounds and premises doubles worker zero's memory, so shells only do so when asked.
+    let provenance = commands || !explain.is_empty() || !why_not.is_empty();
+    assert!(!commands || interactive, "--provenance requires --shell or --serve");
+    let demand_driven = !demanded.is_empty();
+
+    let labels = Labels::new();
+    let operators = Operators::new();
+    let profiled = (labels.clone(), operators.clone());
+    let measured = Arc::new(Mutex::new(BTreeMap::new()));
+    let reported = measured.clone();
+
     // start up timely computation
-    timely::execute_from_args(std::env::args(), |worker| {
+    timely::execute_from_args(args.clone().into_iter(), move |worker| {
+
+        let timer = ::std::time::Instant::now();
+        let mut probe = ProbeHandle::new();
+        let stores = Stores::default();
+        let mut shell = Shell::new(Numbers, 1);
+        let counts: Option<Counts> = if sizes { Some(Counts::default()) } else { None };
+
+        if profile { operators.register(worker); }
 
         // construct streaming scope
-        let (mut c, mut p, mut q, mut r, mut s, mut u) =
-        worker.dataflow::<Time,_,_>(move |outer| {
+        let (mut c, mut p, mut q, mut r, mut s, mut u, mut d) =
+        worker.dataflow::<Time,_,_>(|outer| {
 
             // inputs for base facts; currently not used because no data on hand.
             let (_cin, c) = outer.new_collection::<(Node,Node,Node),Diff>();
@@ -29,79 +103,818 @@ fn main() {
             let (_sin, s) = outer.new_collection::<(Node,Node),Diff>();
             let (_uin, u) = outer.new_collection::<(Node,Node,Node),Diff>();
 
-            // construct iterative derivation scope
-            let (_p, _q) = outer.iterative::<Iter,_,_>(|inner| {
+            // demanded first arguments of p and q, used only when demand-driven.
+            let (_din, demand) = outer.new_collection::<Node,Diff>();
 
-                // use differential_dataflow::operators::iterate;
-                use differential_dataflow::operators::arrange::ArrangeByKey;
-                use differential_dataflow::operators::arrange::ArrangeBySelf;
+            let base = Base { c, p, q, r, s, u };
+            let (p_out, q_out, rounds) = derive(&base, if demand_driven { Some(&demand) } else { None }, &plans, provenance, &labels, &counts);
 
-                // create new variables
-                let p_var = iterate::MonoidVariable::new(inner, Product::new(Default::default(), 1));
-                let q_var = iterate::MonoidVariable::new(inner, Product::new(Default::default(), 1));
+            p_out.probe_with(&mut probe);
+            q_out.probe_with(&mut probe);
 
-                // accumulate
-                let p_new = p_var.distinct();
-                let q_new = q_var.distinct();
+            if demand_driven {
+                p_out.semijoin(&demand)
+                     .inspect(|x| println!("p: {:?}", x));
+                q_out.map(|(x,r,z)| (x,(r,z)))
+                     .semijoin(&demand)
+                     .map(|(x,(r,z))| (x,r,z))
+                     .inspect(|x| println!("q: {:?}", x));
+            }
 
-                // arrangements for p.
-                let p_by0 = p_new.arrange_by_key();
-                let p_by1 = p_new.map_in_place(|(x,y)| std::mem::swap(x,y)).arrange_by_key();
-                let p_by01 = p_new.arrange_by_self();   // TODO: Could be shared with the `distinct`.
+            if !interactive {
+                p_out.map(|_| ()).consolidate().inspect(|x| println!("P: {:?}", x));
+                q_out.map(|_| ()).consolidate().inspect(|x| println!("Q: {:?}", x));
+            }
 
-                // arrangements for q.
-                let q_by0 = q_new.map(|(x,y,z)| (x,(y,z))).arrange_by_key();
-                let q_by1 = q_new.map(|(x,y,z)| (y,(x,z))).arrange_by_key();
-                let q_by01 = q_new.map(|(x,y,z)| ((x,y),z)).arrange_by_key();
-                let q_by21 = q_new.map(|(x,y,z)| ((z,y),x)).arrange_by_key();
+            if interactive || output.is_some() {
+                shell.output("p", 2, &p_out.map(|(x,z)| vec![x,z]), &mut probe);
+                shell.output("q", 3, &q_out.map(|(x,r,z)| vec![x,r,z]), &mut probe);
+            }
 
-                // static relations from outside the iterative scope.
-                let c_by1 = c.enter(inner).map(|(x,y,z)| (y,(x,z))).arrange_by_key();
-                let r_by0 = r.enter(inner).map(|(x,y,z)| (x,(y,z))).arrange_by_key();
-                let s_by0 = s.enter(inner).arrange_by_key();
-                let u_by0 = u.enter(inner).map(|(x,y,z)| (x,(y,z))).arrange_by_key();
+            if let Some((p_rounds, q_rounds)) = rounds {
+                gather(&p_rounds, &stores.p, &stores.index, &mut probe);
+                gather(&q_rounds, &stores.q, &stores.index, &mut probe);
+                gather(&base.p, &stores.p_input, &stores.index, &mut probe);
+                gather(&base.q, &stores.q_input, &stores.index, &mut probe);
+                gather(&base.c, &stores.c, &stores.index, &mut probe);
+                gather(&base.r, &stores.r, &stores.index, &mut probe);
+                gather(&base.s, &stores.s, &stores.index, &mut probe);
+                gather(&base.u, &stores.u, &stores.index, &mut probe);
+            }
 
-                // IR1: p(x,z) := p(x,y), p(y,z)
-                let ir1 = p_by1.join_core(&p_by0, |_y,&x,&z| Some((x,z)));
+            (_cin, _pin, _qin, _rin, _sin, _uin, _din)
+        });
 
-                // IR2: q(x,r,z) := p(x,y), q(y,r,z)
-                let ir2 = p_by1.join_core(&q_by0, |_y,&x,&(r,z)| Some((x,r,z)));
+        let prefix = args.get(1).expect("must specify path prefix");
 
-                // IR3: p(x,z) := p(y,w), u(w,r,z), q(x,r,y)
-                let ir3 = p_by1.join_core(&u_by0, |_w,&y,&(r,z)| Some(((y,r),z)))
-                               .join_core(&q_by21, |_yr,&z,&x| Some((x,z)));
+        for (x,y,z) in load3(worker.index(), &prefix, "c.txt") { c.insert((x,y,z)); }
+        for (x,y)   in load2(worker.index(), &prefix, "p.txt") { p.insert((x,y));   }
+        for (x,y,z) in load3(worker.index(), &prefix, "q.txt") { q.insert((x,y,z)); }
+        for (x,y,z) in load3(worker.index(), &prefix, "r.txt") { r.insert((x,y,z)); }
+        for (x,y)   in load2(worker.index(), &prefix, "s.txt") { s.insert((x,y));   }
+        for (x,y,z) in load3(worker.index(), &prefix, "u.txt") { u.insert((x,y,z)); }
+        if worker.index() == 0 { for &x in demanded.iter() { d.insert(x); } }
+        if worker.index() == 0 { println!("{:?}\tinputs loaded", timer.elapsed()); }
 
-                // IR4: p(x,z) := c(y,w,z), p(x,w), p(x,y)
-                let ir4 = c_by1.join_core(&p_by1, |_w,&(y,z),&x| Some(((x,y),z)))
-                               .join_core(&p_by01, |&(x,_y),&z,&()| Some((x,z)));
+        if provenance || demand_driven || interactive {
 
-                // IR5: q(x,q,z) := q(x,r,z), s(r,q)
-                let ir5 = q_by1.join_core(&s_by0, |_r,&(x,z),&q| Some((x,q,z)));
+            c.advance_to(1); c.flush();
+            p.advance_to(1); p.flush();
+            q.advance_to(1); q.flush();
+            r.advance_to(1); r.flush();
+            s.advance_to(1); s.flush();
+            u.advance_to(1); u.flush();
+            d.advance_to(1); d.flush();
 
-                // IR6: q(x,e,o) := q(x,y,z), r(y,u,e), q(z,u,o)
-                let ir6 = q_by1.join_core(&r_by0, |_y,&(x,z),&(u,e)| Some(((z,u),(x,e))))
-                               .join_core(&q_by01, |_zu,&(x,e),&o| Some((x,e,o)));
+            while probe.less_than(d.time()) { worker.step(); }
+            println!("{:?}\tcomputation initialized", timer.elapsed());
+            if let (0, Some(directory)) = (worker.index(), output.as_ref()) {
+                shell.write(Path::new(directory)).expect("failed to write outputs");
+            }
+        }
 
-                p_var.set(&p.enter(inner).concatenate(vec![ir1, ir3, ir4]));
-                q_var.set(&q.enter(inner).concatenate(vec![ir2, ir5, ir6]));
+        if provenance {
+            if worker.index() == 0 {
+                let facts = stores.facts();
+                for fact in explain.iter() {
+                    facts.explain(*fact, &mut std::io::stdout()).expect("failed to write explanation");
+                }
+                for fact in why_not.iter() {
+                    facts.why_not(*fact, 0, &mut HashSet::new(), &mut std::io::stdout()).expect("failed to write report");
+                }
+            }
+        }
 
-                // return the derived p and q
-                (p_new.leave(), q_new.leave())
-            });
+        if interactive {
+            if worker.index() == 0 {
+                shell.input("c", c);
+                shell.input("p", p);
+                shell.input("q", q);
+                shell.input("r", r);
+                shell.input("s", s);
+                shell.input("u", u);
+                if demand_driven { shell.input("demand", d); }
+                else { d.close(); }
+                if provenance {
+                    let explained = stores.clone();
+                    shell.command("explain <fact>", "derivation tree for p:x,z or q:x,r,z", move |words, out| {
+                        let fact = words.first().and_then(|word| Fact::parse(word)).ok_or("expected a fact such as p:1,2")?;
+                        explained.facts().explain(fact, out).map_err(|e| e.to_string())
+                    });
+                    let reported = stores.clone();
+                    shell.command("why-not <fact>", "why p:x,z or q:x,r,z was not derived", move |words, out| {
+                        let fact = words.first().and_then(|word| Fact::parse(word)).ok_or("expected a fact such as p:1,2")?;
+                        reported.facts().why_not(fact, 0, &mut HashSet::new(), out).map_err(|e| e.to_string())
+                    });
+                }
+                match serve.as_ref() {
+                    Some(address) => differential::server::serve(&mut shell, address, worker, &probe).expect("server failed"),
+                    None => shell.run(worker, &probe),
+                }
+            }
+        }
+        else if demand_driven {
 
-            (_cin, _pin, _qin, _rin, _sin, _uin)
-        });
+            // Base facts stay fixed from here on; only the demanded nodes change.
+            c.close(); p.close(); q.close(); r.close(); s.close(); u.close();
 
-        let prefix = std::env::args().nth(1).expect("must specify path prefix");
+            // Worker zero reads demand changes, one epoch per line.
+            if worker.index() == 0 {
+                use std::io::BufRead;
+                let stdin = std::io::stdin();
+                for line in stdin.lock().lines() {
+                    let line = line.expect("failed to read stdin");
+                    let mut words = line.split_whitespace();
+                    match (words.next(), words.next().and_then(|x| x.parse::<Node>().ok())) {
+                        (Some("+"), Some(x)) => d.insert(x),
+                        (Some("-"), Some(x)) => d.remove(x),
+                        _ => { println!("expected `+ node` or `- node`"); continue; }
+                    }
+                    let next = d.time() + 1;
+                    d.advance_to(next); d.flush();
+                    while probe.less_than(d.time()) { worker.step(); }
+                    println!("{:?}\tdemand updated", timer.elapsed());
+                }
+            }
+        }
+        else {
+            c.close(); p.close(); q.close(); r.close(); s.close(); u.close(); d.close();
+            while worker.step() { }
+            println!("{:?}\tcomplete", timer.elapsed());
+            if let (0, Some(directory)) = (worker.index(), output.as_ref()) {
+                shell.write(Path::new(directory)).expect("failed to write outputs");
+            }
+        }
 
-        for (x,y,z) in load3(worker.index(), &prefix, "c.txt") { c.insert((x,y,z)); }
-        for (x,y)   in load2(worker.index(), &prefix, "p.txt") { p.insert((x,y));   }
-        for (x,y,z) in load3(worker.index(), &prefix, "q.txt") { q.insert((x,y,z)); }
-        for (x,y,z) in load3(worker.index(), &prefix, "r.txt") { r.insert((x,y,z)); }
-        for (x,y)   in load2(worker.index(), &prefix, "s.txt") { s.insert((x,y));   }
-        for (x,y,z) in load3(worker.index(), &prefix, "u.txt") { u.insert((x,y,z)); }
+        if let Some(counts) = counts {
+            let mut measured = measured.lock().expect("sizes poisoned");
+            for (stage, count) in counts.borrow().iter() {
+                *measured.entry(stage.clone()).or_insert(0) += count;
+            }
+        }
 
     }).unwrap();
+
+    // Workers have finished and flushed their logs.
+    if profile { profiled.1.profile(&profiled.0); }
+    if sizes {
+        for (stage, count) in reported.lock().expect("sizes poisoned").iter() {
+            println!("{:<24}{:>16} updates", stage, count);
+        }
+    }
+}
+
+/// The base relations of an ontology.
+struct Base<G: Scope> {
+    c: Collection<G, (Node,Node,Node), Diff>,
+    p: Collection<G, (Node,Node), Diff>,
+    q: Collection<G, (Node,Node,Node), Diff>,
+    r: Collection<G, (Node,Node,Node), Diff>,
+    s: Collection<G, (Node,Node), Diff>,
+    u: Collection<G, (Node,Node,Node), Diff>,
+}
+
+/// Derived p and q facts, each with the iteration at which it first appears.
+type Rounds<G> = (Collection<G, ((Node,Node),Iter), Diff>, Collection<G, ((Node,Node,Node),Iter), Diff>);
+
+/// Derives p and q from `base` by the rules IR1 through IR6, joining in the orders `plans` names.
+///
+/// With `demand`, p and q hold only the facts that facts about demanded nodes depend on. With
+/// `provenance`, the iteration at which each fact first appears is returned as well.
+fn derive<G: Scope<Timestamp=Time>>(
+    base: &Base<G>,
+    demand: Option<&Collection<G, Node, Diff>>,
+    plans: &HashMap<&str, &str>,
+    provenance: bool,
+    labels: &Labels,
+    counts: &Option<Counts>,
+) -> (Collection<G, (Node,Node), Diff>, Collection<G, (Node,Node,Node), Diff>, Option<Rounds<G>>) {
+
+    base.p.scope().iterative::<Iter,_,_>(|inner| {
+
+        // use differential_dataflow::operators::iterate;
+        use differential_dataflow::operators::arrange::ArrangeByKey;
+        use differential_dataflow::operators::arrange::ArrangeBySelf;
+
+        labels.label(inner, "p, q");
+
+        // create new variables
+        let p_var = iterate::MonoidVariable::new(inner, Product::new(Default::default(), 1));
+        let q_var = iterate::MonoidVariable::new(inner, Product::new(Default::default(), 1));
+
+        // accumulate
+        let p_new = p_var.distinct();
+        let q_new = q_var.distinct();
+
+        labels.label(inner, "p arrangements");
+
+        // arrangements for p.
+        let p_by0 = p_new.arrange_by_key();
+        let p_by1 = p_new.map_in_place(|(x,y)| std::mem::swap(x,y)).arrange_by_key();
+        let p_by01 = p_new.arrange_by_self();   // TODO: Could be shared with the `distinct`.
+
+        labels.label(inner, "q arrangements");
+
+        // arrangements for q.
+        let q_by0 = q_new.map(|(x,y,z)| (x,(y,z))).arrange_by_key();
+        let q_by1 = q_new.map(|(x,y,z)| (y,(x,z))).arrange_by_key();
+        let q_by01 = q_new.map(|(x,y,z)| ((x,y),z)).arrange_by_key();
+        let q_by21 = q_new.map(|(x,y,z)| ((z,y),x)).arrange_by_key();
+
+        labels.label(inner, "c, r, s, u arrangements");
+
+        // static relations from outside the iterative scope.
+        let c_by1 = base.c.enter(inner).map(|(x,y,z)| (y,(x,z))).arrange_by_key();
+        let r_by0 = base.r.enter(inner).map(|(x,y,z)| (x,(y,z))).arrange_by_key();
+        let s_by0 = base.s.enter(inner).arrange_by_key();
+        let u_by0 = base.u.enter(inner).map(|(x,y,z)| (x,(y,z))).arrange_by_key();
+
+        labels.label(inner, "IR1");
+        // IR1: p(x,z) := p(x,y), p(y,z)
+        let ir1 = p_by1.join_core(&p_by0, |_y,&x,&z| Some((x,z)));
+
+        labels.label(inner, "IR2");
+        // IR2: q(x,r,z) := p(x,y), q(y,r,z)
+        let ir2 = p_by1.join_core(&q_by0, |_y,&x,&(r,z)| Some((x,r,z)));
+
+        labels.label(inner, "IR3");
+        // IR3: p(x,z) := p(y,w), u(w,r,z), q(x,r,y)
+        let plan = plans["IR3"];
+        let ir3 = match plan {
+            // p and u on w, then q on (y,r).
+            "pu" => {
+                let first = p_by1.join_core(&u_by0, |_w,&y,&(r,z)| Some(((y,r),z)));
+                measure(&first, &format!("IR3 {}: first join", plan), counts);
+                first.join_core(&q_by21, |_yr,&z,&x| Some((x,z)))
+            },
+            // u and q on r, then p on (y,w). Roles are few, so each meets many facts.
+            "uq" => {
+                let u_by1 = base.u.enter(inner).map(|(w,r,z)| (r,(w,z))).arrange_by_key();
+                let first = u_by1.join_core(&q_by1, |_r,&(w,z),&(x,y)| Some(((y,w),(x,z))));
+                measure(&first, &format!("IR3 {}: first join", plan), counts);
+                first.join_core(&p_by01, |_yw,&(x,z),&()| Some((x,z)))
+            },
+            // p and q on y, then u on (w,r).
+            _ => {
+                let q_by2 = q_new.map(|(x,r,y)| (y,(x,r))).arrange_by_key();
+                let u_by01 = base.u.enter(inner).map(|(w,r,z)| ((w,r),z)).arrange_by_key();
+                let first = p_by0.join_core(&q_by2, |_y,&w,&(x,r)| Some(((w,r),x)));
+                measure(&first, &format!("IR3 {}: first join", plan), counts);
+                first.join_core(&u_by01, |_wr,&x,&z| Some((x,z)))
+            },
+        };
+        measure(&ir3, &format!("IR3 {}: output", plan), counts);
+
+        labels.label(inner, "IR4");
+        // IR4: p(x,z) := c(y,w,z), p(x,w), p(x,y)
+        let plan = plans["IR4"];
+        let ir4 = match plan {
+            // c and p(x,w) on w, then p on (x,y).
+            "cw" => {
+                let first = c_by1.join_core(&p_by1, |_w,&(y,z),&x| Some(((x,y),z)));
+                measure(&first, &format!("IR4 {}: first join", plan), counts);
+                first.join_core(&p_by01, |&(x,_y),&z,&()| Some((x,z)))
+            },
+            // c and p(x,y) on y, then p on (x,w).
+            "cy" => {
+                let c_by0 = base.c.enter(inner).map(|(y,w,z)| (y,(w,z))).arrange_by_key();
+                let first = c_by0.join_core(&p_by1, |_y,&(w,z),&x| Some(((x,w),z)));
+                measure(&first, &format!("IR4 {}: first join", plan), counts);
+                first.join_core(&p_by01, |&(x,_w),&z,&()| Some((x,z)))
+            },
+            // p and p on x, then c on (y,w): every pair of superclasses of each x.
+            _ => {
+                let c_by01 = base.c.enter(inner).map(|(y,w,z)| ((y,w),z)).arrange_by_key();
+                let first = p_by0.join_core(&p_by0, |&x,&w,&y| Some(((y,w),x)));
+                measure(&first, &format!("IR4 {}: first join", plan), counts);
+                first.join_core(&c_by01, |_yw,&x,&z| Some((x,z)))
+            },
+        };
+        measure(&ir4, &format!("IR4 {}: output", plan), counts);
+
+        labels.label(inner, "IR5");
+        // IR5: q(x,q,z) := q(x,r,z), s(r,q)
+        let ir5 = q_by1.join_core(&s_by0, |_r,&(x,z),&q| Some((x,q,z)));
+
+        labels.label(inner, "IR6");
+        // IR6: q(x,e,o) := q(x,y,z), r(y,u,e), q(z,u,o)
+        let plan = plans["IR6"];
+        let ir6 = match plan {
+            // q(x,y,z) and r on y, then q on (z,u).
+            "qr" => {
+                let first = q_by1.join_core(&r_by0, |_y,&(x,z),&(u,e)| Some(((z,u),(x,e))));
+                measure(&first, &format!("IR6 {}: first join", plan), counts);
+                first.join_core(&q_by01, |_zu,&(x,e),&o| Some((x,e,o)))
+            },
+            // r and q(z,u,o) on u, then q on (y,z). Roles are few, so each meets many facts.
+            "rq" => {
+                let r_by1 = base.r.enter(inner).map(|(y,u,e)| (u,(y,e))).arrange_by_key();
+                let q_by12 = q_new.map(|(x,y,z)| ((y,z),x)).arrange_by_key();
+                let first = r_by1.join_core(&q_by1, |_u,&(y,e),&(z,o)| Some(((y,z),(e,o))));
+                measure(&first, &format!("IR6 {}: first join", plan), counts);
+                first.join_core(&q_by12, |_yz,&(e,o),&x| Some((x,e,o)))
+            },
+            // q and q on z, then r on (y,u).
+            _ => {
+                let q_by2 = q_new.map(|(x,y,z)| (z,(x,y))).arrange_by_key();
+                let r_by01 = base.r.enter(inner).map(|(y,u,e)| ((y,u),e)).arrange_by_key();
+                let first = q_by2.join_core(&q_by0, |_z,&(x,y),&(u,o)| Some(((y,u),(x,o))));
+                measure(&first, &format!("IR6 {}: first join", plan), counts);
+                first.join_core(&r_by01, |_yu,&(x,o),&e| Some((x,e,o)))
+            },
+        };
+        measure(&ir6, &format!("IR6 {}: output", plan), counts);
+
+        labels.label(inner, "p, q (union)");
+        let p_next = base.p.enter(inner).concatenate(vec![ir1, ir3, ir4]);
+        let q_next = base.q.enter(inner).concatenate(vec![ir2, ir5, ir6]);
+
+        // Demand transformation: every rule for p(x,_) or q(x,_,_) reads p and q only at x
+        // itself, at y for p(x,y) (IR1, IR2), and at y for q(x,_,y) (IR3, IR6). Closing the
+        // demanded nodes under those two steps gives the magic set, to which p and q can be
+        // restricted without losing any fact about a demanded node.
+        labels.label(inner, "demand");
+        let (p_next, q_next) = if let Some(demand) = demand {
+            let magic_var = iterate::MonoidVariable::new(inner, Product::new(Default::default(), 1));
+            let magic = magic_var.distinct();
+            magic_var.set(&demand.enter(inner).concatenate(vec![
+                p_new.map(|(_x,y)| y),
+                q_new.map(|(_x,_r,y)| y),
+            ]));
+            (
+                p_next.semijoin(&magic),
+                q_next.map(|(x,r,z)| (x,(r,z))).semijoin(&magic).map(|(x,(r,z))| (x,r,z)),
+            )
+        }
+        else { (p_next, q_next) };
+
+        labels.label(inner, "p, q (feedback)");
+        p_var.set(&p_next);
+        q_var.set(&q_next);
+
+        // Input facts enter at iteration 1, and each derived fact first appears one iteration
+        // after its shortest derivation's premises. Recording that iteration lets us rebuild
+        // a minimal derivation tree afterwards.
+        labels.label(inner, "provenance");
+        let rounds = if provenance {
+            let p_rounds = p_new.inner.map(|(pxz, time, diff)| ((pxz, time.inner), time, diff)).as_collection();
+            let q_rounds = q_new.inner.map(|(qxrz, time, diff)| ((qxrz, time.inner), time, diff)).as_collection();
+            Some((p_rounds.leave(), q_rounds.leave()))
+        }
+        else { None };
+
+        // return the derived p and q
+        (p_new.leave(), q_new.leave(), rounds)
+    })
+}
+
+/// Join orders for the three-way rules, named by the two atoms joined first; the first is the default.
+///
+/// The default orders avoid joining on roles, which are few and so shared by many facts; the
+/// others reproduce the plans that make these rules expensive for some engines.
+const PLANS: &[(&str, &[&str])] = &[
+    ("IR3", &["pu", "uq", "pq"]),
+    ("IR4", &["cw", "cy", "pp"]),
+    ("IR6", &["qr", "rq", "qq"]),
+];
+
+/// Updates produced at each measured stage, by one worker.
+type Counts = Rc<RefCell<BTreeMap<String, usize>>>;
+
+/// Counts the updates `collection` produces as `stage`, if counting.
+fn measure<G: Scope, D: Data>(collection: &Collection<G, D, Diff>, stage: &str, counts: &Option<Counts>) {
+    if let Some(counts) = counts {
+        let counts = counts.clone();
+        let stage = stage.to_owned();
+        collection.inspect(move |&(_, _, diff)| {
+            *counts.borrow_mut().entry(stage.clone()).or_insert(0) += diff.abs() as usize;
+        });
+    }
+}
+
+/// A fact from one of the derived or base relations.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+enum Fact {
+    P(Node, Node),
+    Q(Node, Node, Node),
+    C(Node, Node, Node),
+    R(Node, Node, Node),
+    S(Node, Node),
+    U(Node, Node, Node),
+}
+
+impl Fact {
+    /// Forms a fact from a relation name and its values.
+    fn new(relation: &str, values: &[Node]) -> Fact {
+        match (relation, values) {
+            ("p", &[x,z]) => Fact::P(x,z),
+            ("q", &[x,r,z]) => Fact::Q(x,r,z),
+            ("c", &[y,w,z]) => Fact::C(y,w,z),
+            ("r", &[r,p,e]) => Fact::R(r,p,e),
+            ("s", &[r,p]) => Fact::S(r,p),
+            ("u", &[r,z,w]) => Fact::U(r,z,w),
+            _ => panic!("unknown relation: {}/{}", relation, values.len()),
+        }
+    }
+    /// The relation name and values of the fact.
+    fn parts(&self) -> (&'static str, Vec<Node>) {
+        match *self {
+            Fact::P(x,z) => ("p", vec![x,z]),
+            Fact::Q(x,r,z) => ("q", vec![x,r,z]),
+            Fact::C(y,w,z) => ("c", vec![y,w,z]),
+            Fact::R(r,p,e) => ("r", vec![r,p,e]),
+            Fact::S(r,p) => ("s", vec![r,p]),
+            Fact::U(r,z,w) => ("u", vec![r,z,w]),
+        }
+    }
+    /// Parses facts of the form `p:x,z` or `q:x,r,z`.
+    fn parse(text: &str) -> Option<Fact> {
+        let mut parts = text.splitn(2, ':');
+        let name = parts.next()?;
+        let nodes = parts.next()?.split(',').map(|x| x.trim().parse().ok()).collect::<Option<Vec<Node>>>()?;
+        match (name, &nodes[..]) {
+            ("p", &[x,z]) => Some(Fact::P(x,z)),
+            ("q", &[x,r,z]) => Some(Fact::Q(x,r,z)),
+            _ => None,
+        }
+    }
+}
+
+impl ::std::fmt::Display for Fact {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Fact::P(x,z) => write!(f, "p({},{})", x, z),
+            Fact::Q(x,r,z) => write!(f, "q({},{},{})", x, r, z),
+            Fact::C(y,w,z) => write!(f, "c({},{},{})", y, w, z),
+            Fact::R(r,p,e) => write!(f, "r({},{},{})", r, p, e),
+            Fact::S(r,p) => write!(f, "s({},{})", r, p),
+            Fact::U(r,z,w) => write!(f, "u({},{},{})", r, z, w),
+        }
+    }
+}
+
+/// An atom in a rule: a relation name and a variable for each column.
+type Atom = (&'static str, &'static [&'static str]);
+
+/// Galen's rules, as (name, head, body) in the order they appear in `query.dl`.
+const RULES: &[(&str, Atom, &[Atom])] = &[
+    ("IR1", ("p", &["x","z"]), &[("p", &["x","y"]), ("p", &["y","z"])]),
+    ("IR2", ("q", &["x","r","z"]), &[("p", &["x","y"]), ("q", &["y","r","z"])]),
+    ("IR3", ("p", &["x","z"]), &[("p", &["y","w"]), ("u", &["w","r","z"]), ("q", &["x","r","y"])]),
+    ("IR4", ("p", &["x","z"]), &[("c", &["y","w","z"]), ("p", &["x","w"]), ("p", &["x","y"])]),
+    ("IR5", ("q", &["x","q","z"]), &[("q", &["x","r","z"]), ("s", &["r","q"])]),
+    ("IR6", ("q", &["x","e","o"]), &[("q", &["x","y","z"]), ("r", &["y","u","e"]), ("q", &["z","u","o"])]),
+];
+
+/// Renders an atom, substituting bound variables and marking free variables with `?`.
+fn render(atom: Atom, binding: &HashMap<&'static str, Node>) -> String {
+    let terms = atom.1.iter().map(|var| match binding.get(var) {
+        Some(value) => value.to_string(),
+        None => format!("?{}", var),
+    }).collect::<Vec<_>>();
+    format!("{}({})", atom.0, terms.join(","))
+}
+
+/// Number of failing bindings printed, and the depth to which missing premises are pursued.
+const WHY_NOT_SAMPLES: usize = 3;
+const WHY_NOT_DEPTH: usize = 3;
+
+type Store<D> = Rc<RefCell<HashMap<D, Diff>>>;
+type Index = Rc<RefCell<Option<Rc<Facts>>>>;
+
+/// Accumulated contents of relations, gathered at worker zero.
+#[derive(Default, Clone)]
+struct Stores {
+    p: Store<((Node,Node),Iter)>,
+    q: Store<((Node,Node,Node),Iter)>,
+    p_input: Store<(Node,Node)>,
+    q_input: Store<(Node,Node,Node)>,
+    c: Store<(Node,Node,Node)>,
+    r: Store<(Node,Node,Node)>,
+    s: Store<(Node,Node)>,
+    u: Store<(Node,Node,Node)>,
+    /// The index built from the stores, dropped whenever one of them changes.
+    index: Index,
+}
+
+impl Stores {
+    /// The index of the gathered relations, rebuilt only if they have changed since it was built.
+    fn facts(&self) -> Rc<Facts> {
+        let mut index = self.index.borrow_mut();
+        if index.is_none() { *index = Some(Rc::new(Facts::from(self))); }
+        index.as_ref().unwrap().clone()
+    }
+}
+
+/// Exchanges `collection` to worker zero and accumulates its changes into `store`, dropping `index`.
+fn gather<G, D>(collection: &Collection<G, D, Diff>, store: &Store<D>, index: &Index, probe: &mut ProbeHandle<Time>)
+where G: Scope<Timestamp=Time>, D: Data+::std::hash::Hash {
+    let store = store.clone();
+    let index = index.clone();
+    collection
+        .inner
+        .exchange(|_| 0)
+        .as_collection()
+        .inspect(move |(datum, _time, diff)| {
+            *index.borrow_mut() = None;
+            let mut store = store.borrow_mut();
+            let remove = {
+                let count = store.entry(datum.clone()).or_insert(0);
+                *count += *diff;
+                *count == 0
+            };
+            if remove { store.remove(datum); }
+        })
+        .probe_with(probe);
+}
+
+/// Indexed snapshot of gathered relations, from which derivations are reconstructed.
+struct Facts {
+    p: HashMap<(Node,Node),Iter>,
+    q: HashMap<(Node,Node,Node),Iter>,
+    p_input: HashSet<(Node,Node)>,
+    q_input: HashSet<(Node,Node,Node)>,
+    p_by0: HashMap<Node, Vec<Node>>,
+    q_by0: HashMap<Node, Vec<(Node,Node)>>,
+    c_by2: HashMap<Node, Vec<(Node,Node)>>,
+    r_by2: HashMap<Node, Vec<(Node,Node)>>,
+    s_by1: HashMap<Node, Vec<Node>>,
+    u: HashSet<(Node,Node,Node)>,
+}
+
+impl<'a> From<&'a Stores> for Facts {
+    fn from(stores: &'a Stores) -> Self {
+
+        let mut p = HashMap::new();
+        for (&((x,z),round), &diff) in stores.p.borrow().iter() {
+            if diff > 0 {
+                let entry = p.entry((x,z)).or_insert(round);
+                if *entry > round { *entry = round; }
+            }
+        }
+        let mut q = HashMap::new();
+        for (&((x,r,z),round), &diff) in stores.q.borrow().iter() {
+            if diff > 0 {
+                let entry = q.entry((x,r,z)).or_insert(round);
+                if *entry > round { *entry = round; }
+            }
+        }
+
+        let mut p_by0 = HashMap::new();
+        for &(x,z) in p.keys() { p_by0.entry(x).or_insert(Vec::new()).push(z); }
+        let mut q_by0 = HashMap::new();
+        for &(x,r,z) in q.keys() { q_by0.entry(x).or_insert(Vec::new()).push((r,z)); }
+        let mut c_by2 = HashMap::new();
+        for (&(y,w,z), _) in stores.c.borrow().iter().filter(|x| *x.1 > 0) { c_by2.entry(z).or_insert(Vec::new()).push((y,w)); }
+        let mut r_by2 = HashMap::new();
+        for (&(y,u,e), _) in stores.r.borrow().iter().filter(|x| *x.1 > 0) { r_by2.entry(e).or_insert(Vec::new()).push((y,u)); }
+        let mut s_by1 = HashMap::new();
+        for (&(r,q), _) in stores.s.borrow().iter().filter(|x| *x.1 > 0) { s_by1.entry(q).or_insert(Vec::new()).push(r); }
+        let u = stores.u.borrow().iter().filter(|x| *x.1 > 0).map(|x| *x.0).collect();
+        let p_input = stores.p_input.borrow().iter().filter(|x| *x.1 > 0).map(|x| *x.0).collect();
+        let q_input = stores.q_input.borrow().iter().filter(|x| *x.1 > 0).map(|x| *x.0).collect();
+
+        Facts { p, q, p_input, q_input, p_by0, q_by0, c_by2, r_by2, s_by1, u }
+    }
+}
+
+impl Facts {
+
+    /// The iteration at which a derived fact first appears, if it is present.
+    fn round(&self, fact: Fact) -> Option<Iter> {
+        match fact {
+            Fact::P(x,z) => self.p.get(&(x,z)).cloned(),
+            Fact::Q(x,r,z) => self.q.get(&(x,r,z)).cloned(),
+            _ => None,
+        }
+    }
+
+    /// True if `fact` is an input fact, rather than one that must be derived.
+    fn input(&self, fact: Fact) -> bool {
+        match fact {
+            Fact::P(x,z) => self.p_input.contains(&(x,z)),
+            Fact::Q(x,r,z) => self.q_input.contains(&(x,r,z)),
+            _ => true,
+        }
+    }
+
+    /// True if `fact` is present and was derived strictly before iteration `round`.
+    fn before(&self, fact: Fact, round: Iter) -> bool {
+        self.round(fact).map(|r| r < round).unwrap_or(false)
+    }
+
+    /// Finds a rule instance producing `fact` from facts of strictly earlier rounds.
+    ///
+    /// Following these instances from any derived fact yields a derivation tree of
+    /// minimal depth, as each premise was itself derived in fewer iterations.
+    fn derivation(&self, fact: Fact, round: Iter) -> Option<(&'static str, Vec<Fact>)> {
+        let empty1 = Vec::new();
+        let empty2 = Vec::new();
+        match fact {
+            Fact::P(x,z) => {
+                // IR1: p(x,z) := p(x,y), p(y,z)
+                for &y in self.p_by0.get(&x).unwrap_or(&empty1) {
+                    if self.before(Fact::P(x,y), round) && self.before(Fact::P(y,z), round) {
+                        return Some(("IR1", vec![Fact::P(x,y), Fact::P(y,z)]));
+                    }
+                }
+                // IR3: p(x,z) := p(y,w), u(w,r,z), q(x,r,y)
+                for &(r,y) in self.q_by0.get(&x).unwrap_or(&empty2) {
+                    if self.before(Fact::Q(x,r,y), round) {
+                        for &w in self.p_by0.get(&y).unwrap_or(&empty1) {
+                            if self.before(Fact::P(y,w), round) && self.u.contains(&(w,r,z)) {
+                                return Some(("IR3", vec![Fact::P(y,w), Fact::U(w,r,z), Fact::Q(x,r,y)]));
+                            }
+                        }
+                    }
+                }
+                // IR4: p(x,z) := c(y,w,z), p(x,w), p(x,y)
+                for &(y,w) in self.c_by2.get(&z).unwrap_or(&empty2) {
+                    if self.before(Fact::P(x,w), round) && self.before(Fact::P(x,y), round) {
+                        return Some(("IR4", vec![Fact::C(y,w,z), Fact::P(x,w), Fact::P(x,y)]));
+                    }
+                }
+            },
+            Fact::Q(x,r,z) => {
+                // IR2: q(x,r,z) := p(x,y), q(y,r,z)
+                for &y in self.p_by0.get(&x).unwrap_or(&empty1) {
+                    if self.before(Fact::P(x,y), round) && self.before(Fact::Q(y,r,z), round) {
+                        return Some(("IR2", vec![Fact::P(x,y), Fact::Q(y,r,z)]));
+                    }
+                }
+                // IR5: q(x,q,z) := q(x,r,z), s(r,q)
+                for &r2 in self.s_by1.get(&r).unwrap_or(&empty1) {
+                    if self.before(Fact::Q(x,r2,z), round) {
+                        return Some(("IR5", vec![Fact::Q(x,r2,z), Fact::S(r2,r)]));
+                    }
+                }
+                // IR6: q(x,e,o) := q(x,y,z), r(y,u,e), q(z,u,o)
+                for &(y,w) in self.q_by0.get(&x).unwrap_or(&empty2) {
+                    if self.before(Fact::Q(x,y,w), round) {
+                        for &(y2,u) in self.r_by2.get(&r).unwrap_or(&empty2) {
+                            if y == y2 && self.before(Fact::Q(w,u,z), round) {
+                                return Some(("IR6", vec![Fact::Q(x,y,w), Fact::R(y,u,r), Fact::Q(w,u,z)]));
+                            }
+                        }
+                    }
+                }
+            },
+            _ => { },
+        }
+        None
+    }
+
+    /// Writes a minimal derivation tree for `fact` to `out`.
+    fn explain(&self, fact: Fact, out: &mut dyn Write) -> ::std::io::Result<()> {
+        if self.round(fact).is_some() {
+            self.print(fact, 0, &mut HashSet::new(), out)
+        }
+        else {
+            writeln!(out, "{}\tnot derived (see --why-not)", fact)
+        }
+    }
+
+    /// True if `fact` is present among the gathered relations.
+    fn contains(&self, fact: Fact) -> bool {
+        let (relation, values) = fact.parts();
+        !self.matching(relation, &values.into_iter().map(Some).collect::<Vec<_>>()).is_empty()
+    }
+
+    /// Tuples of `relation` agreeing with the bound columns of `pattern`.
+    fn matching(&self, relation: &str, pattern: &[Option<Node>]) -> Vec<Vec<Node>> {
+        let mut tuples = Vec::new();
+        match relation {
+            "p" => match pattern[0] {
+                Some(x) => if let Some(zs) = self.p_by0.get(&x) { tuples.extend(zs.iter().map(|&z| vec![x,z])); },
+                None => tuples.extend(self.p.keys().map(|&(x,z)| vec![x,z])),
+            },
+            "q" => match pattern[0] {
+                Some(x) => if let Some(rzs) = self.q_by0.get(&x) { tuples.extend(rzs.iter().map(|&(r,z)| vec![x,r,z])); },
+                None => tuples.extend(self.q.keys().map(|&(x,r,z)| vec![x,r,z])),
+            },
+            "c" => for (&z, yws) in self.c_by2.iter().filter(|x| pattern[2].map(|z| z == *x.0).unwrap_or(true)) {
+                tuples.extend(yws.iter().map(|&(y,w)| vec![y,w,z]));
+            },
+            "r" => for (&e, yus) in self.r_by2.iter().filter(|x| pattern[2].map(|e| e == *x.0).unwrap_or(true)) {
+                tuples.extend(yus.iter().map(|&(y,u)| vec![y,u,e]));
+            },
+            "s" => for (&q, rs) in self.s_by1.iter().filter(|x| pattern[1].map(|q| q == *x.0).unwrap_or(true)) {
+                tuples.extend(rs.iter().map(|&r| vec![r,q]));
+            },
+            "u" => tuples.extend(self.u.iter().map(|&(w,r,z)| vec![w,r,z])),
+            _ => panic!("unknown relation: {}", relation),
+        }
+        tuples.retain(|tuple| tuple.iter().zip(pattern.iter()).all(|(value, bound)| bound.map(|b| b == *value).unwrap_or(true)));
+        tuples
+    }
+
+    /// Reports, for each rule that could produce `fact`, the first body atom without support.
+    ///
+    /// Atoms are tried most-bound first, starting from the head's bindings. When the failing
+    /// atom is fully bound its absence is explained in turn, to a bounded depth.
+    fn why_not(&self, fact: Fact, depth: usize, visited: &mut HashSet<Fact>, out: &mut dyn Write) -> ::std::io::Result<()> {
+
+        let indent = " ".repeat(6 * depth);
+        let (relation, values) = fact.parts();
+
+        if self.contains(fact) {
+            writeln!(out, "{}{}\t[present]", indent, fact)?;
+            return Ok(());
+        }
+        if relation != "p" && relation != "q" {
+            writeln!(out, "{}{}\t[not an input fact]", indent, fact)?;
+            return Ok(());
+        }
+        if !visited.insert(fact) {
+            writeln!(out, "{}{}\t[not derived, see above]", indent, fact)?;
+            return Ok(());
+        }
+        writeln!(out, "{}{}\t[not derived]", indent, fact)?;
+        if depth >= WHY_NOT_DEPTH {
+            return Ok(());
+        }
+
+        for &(rule, head, body) in RULES.iter().filter(|rule| (rule.1).0 == relation) {
+
+            let atoms = body.iter().map(|atom| render(*atom, &HashMap::new())).collect::<Vec<_>>();
+            writeln!(out, "{}  {}: {} :- {}.", indent, rule, render(head, &HashMap::new()), atoms.join(", "))?;
+
+            let mut binding = HashMap::new();
+            for (var, value) in head.1.iter().zip(values.iter()) {
+                binding.insert(*var, *value);
+            }
+            let mut bindings = vec![binding];
+            let mut remaining = (0 .. body.len()).collect::<Vec<_>>();
+
+            while !remaining.is_empty() {
+
+                // next atom: the most bound columns, earliest in the rule on ties.
+                let bound = |atom: Atom| atom.1.iter().filter(|var| bindings[0].contains_key(*var)).count();
+                let position = (0 .. remaining.len()).max_by_key(|&i| (bound(body[remaining[i]]), remaining.len() - i)).unwrap();
+                let index = remaining.remove(position);
+                let atom = body[index];
+
+                let mut extended = Vec::new();
+                for binding in bindings.iter() {
+                    let pattern = atom.1.iter().map(|var| binding.get(var).cloned()).collect::<Vec<_>>();
+                    for tuple in self.matching(atom.0, &pattern) {
+                        let mut binding = binding.clone();
+                        if atom.1.iter().zip(tuple.iter()).all(|(var, value)| *binding.entry(*var).or_insert(*value) == *value) {
+                            extended.push(binding);
+                        }
+                    }
+                }
+
+                if extended.is_empty() {
+                    // distinct instances of the failing atom under the bindings that reached it.
+                    let mut instances = bindings.iter().map(|binding| atom.1.iter().map(|var| binding.get(var).cloned()).collect::<Vec<_>>()).collect::<Vec<_>>();
+                    instances.sort();
+                    instances.dedup();
+                    writeln!(out, "{}    fails at atom {} for {} instance(s):", indent, index + 1, instances.len())?;
+                    for instance in instances.iter().take(WHY_NOT_SAMPLES) {
+                        if instance.iter().all(|value| value.is_some()) {
+                            let values = instance.iter().map(|value| value.unwrap()).collect::<Vec<_>>();
+                            self.why_not(Fact::new(atom.0, &values), depth + 1, visited, out)?;
+                        }
+                        else {
+                            let binding = atom.1.iter().zip(instance.iter()).filter_map(|(var, value)| value.map(|v| (*var, v))).collect();
+                            writeln!(out, "{}      {}\t[no matching facts]", indent, render(atom, &binding))?;
+                        }
+                    }
+                    if instances.len() > WHY_NOT_SAMPLES {
+                        writeln!(out, "{}      ... and {} more", indent, instances.len() - WHY_NOT_SAMPLES)?;
+                    }
+                    break;
+                }
+
+                bindings = extended;
+                if remaining.is_empty() {
+                    writeln!(out, "{}    all atoms satisfied; computation may not have reached fixpoint", indent)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn print(&self, fact: Fact, depth: usize, printed: &mut HashSet<Fact>, out: &mut dyn Write) -> ::std::io::Result<()> {
+        let indent = "  ".repeat(depth);
+        // Input facts may also be derivable, but their trees stop here.
+        if self.input(fact) {
+            return writeln!(out, "{}{}\t[input]", indent, fact);
+        }
+        let round = self.round(fact).expect("premise not present");
+        if printed.insert(fact) {
+            let (rule, premises) = self.derivation(fact, round).expect("derived fact without derivation");
+            writeln!(out, "{}{}\t[{}, round {}]", indent, fact, rule, round)?;
+            for premise in premises {
+                self.print(premise, depth + 1, printed, out)?;
+            }
+            Ok(())
+        }
+        else {
+            writeln!(out, "{}{}\t[see above]", indent, fact)
+        }
+    }
 }
 
 fn read_file(filename: &str) -> impl Iterator<Item=String> {
@@ -136,3 +949,131 @@ fn load3<'a>(index: usize, prefix: &str, filename: &str) -> impl Iterator<Item=(
             )
         })
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    /// Relations as gathered from a run on `p(1,2)`, `p(2,3)`, and `q(3,7,4)`, with each
+    /// derived fact at the iteration it first appears and the inputs at iteration 1.
+    fn fixture() -> Facts {
+        let stores = Stores::default();
+        for &(fact, round) in &[((1,2),1), ((2,3),1), ((1,3),2)] {
+            stores.p.borrow_mut().insert((fact, round), 1);
+        }
+        for &(fact, round) in &[((3,7,4),1), ((2,7,4),2), ((1,7,4),3)] {
+            stores.q.borrow_mut().insert((fact, round), 1);
+        }
+        for &fact in &[(1,2), (2,3)] { stores.p_input.borrow_mut().insert(fact, 1); }
+        stores.q_input.borrow_mut().insert((3,7,4), 1);
+        Facts::from(&stores)
+    }
+
+    fn explain(facts: &Facts, fact: Fact) -> String {
+        let mut out = Vec::new();
+        facts.explain(fact, &mut out).expect("failed to write explanation");
+        String::from_utf8(out).expect("explanation not utf8")
+    }
+
+    #[test]
+    fn explain_input() {
+        assert_eq!(explain(&fixture(), Fact::P(1,2)), "p(1,2)\t[input]\n");
+    }
+
+    #[test]
+    fn explain_derived() {
+        let facts = fixture();
+        assert_eq!(explain(&facts, Fact::P(1,3)), "p(1,3)\t[IR1, round 2]\n  p(1,2)\t[input]\n  p(2,3)\t[input]\n");
+        let tree = explain(&facts, Fact::Q(1,7,4));
+        assert!(tree.starts_with("q(1,7,4)\t[IR2, round 3]\n"), "{}", tree);
+        assert!(tree.contains("q(3,7,4)\t[input]"), "{}", tree);
+    }
+
+    #[test]
+    fn why_not_absent() {
+        let mut out = Vec::new();
+        fixture().why_not(Fact::P(3,1), 0, &mut HashSet::new(), &mut out).expect("failed to write report");
+        let report = String::from_utf8(out).expect("report not utf8");
+        assert!(report.starts_with("p(3,1)\t[not derived]\n"), "{}", report);
+        assert!(report.contains("IR1: p(?x,?z) :- p(?x,?y), p(?y,?z)."), "{}", report);
+    }
+
+    #[test]
+    fn explain_absent() {
+        assert_eq!(explain(&fixture(), Fact::P(3,1)), "p(3,1)\tnot derived (see --why-not)\n");
+    }
+
+    /// Derived p and q, as gathered at worker zero.
+    type Derived = (Store<(Node,Node)>, Store<(Node,Node,Node)>);
+
+    /// Asserts that `demanded` holds exactly the facts of `full` about `nodes`, and less overall.
+    fn agree(full: &Derived, demanded: &Derived, nodes: &[Node]) {
+        let p = |store: &Store<(Node,Node)>| store.borrow().keys().filter(|&&(x,_)| nodes.contains(&x)).cloned().collect::<BTreeSet<_>>();
+        let q = |store: &Store<(Node,Node,Node)>| store.borrow().keys().filter(|&&(x,_,_)| nodes.contains(&x)).cloned().collect::<BTreeSet<_>>();
+        assert!(!p(&full.0).is_empty());
+        assert_eq!(p(&demanded.0), p(&full.0));
+        assert_eq!(q(&demanded.1), q(&full.1));
+        assert!(demanded.0.borrow().len() < full.0.borrow().len());
+    }
+
+    #[test]
+    fn demand_matches_fixpoint() {
+        use differential::generate::galen::{Config, generate};
+        let config = Config { roles: 4, r: 3, s: 3, seed: 1, ..Config::scaled(100) };
+        let directory = ::std::env::temp_dir().join(format!("galen-demand-{}", ::std::process::id()));
+        ::std::fs::create_dir_all(&directory).expect("failed to create directory");
+        generate(&config, &directory).expect("failed to generate ontology");
+        let prefix = format!("{}/", directory.display());
+
+        timely::execute_directly(move |worker| {
+            let mut probe = ProbeHandle::new();
+            let index = Index::default();
+            let full = Derived::default();
+            let demanded = Derived::default();
+            let (mut c, mut p, mut q, mut r, mut s, mut u, mut d) = worker.dataflow::<Time,_,_>(|scope| {
+                let (cin, c) = scope.new_collection::<(Node,Node,Node),Diff>();
+                let (pin, p) = scope.new_collection::<(Node,Node),Diff>();
+                let (qin, q) = scope.new_collection::<(Node,Node,Node),Diff>();
+                let (rin, r) = scope.new_collection::<(Node,Node,Node),Diff>();
+                let (sin, s) = scope.new_collection::<(Node,Node),Diff>();
+                let (uin, u) = scope.new_collection::<(Node,Node,Node),Diff>();
+                let (din, demand) = scope.new_collection::<Node,Diff>();
+                let base = Base { c, p, q, r, s, u };
+                let plans = PLANS.iter().map(|&(rule, plans)| (rule, plans[0])).collect::<HashMap<_,_>>();
+                let labels = Labels::new();
+                let (p_full, q_full, _) = derive(&base, None, &plans, false, &labels, &None);
+                let (p_demanded, q_demanded, _) = derive(&base, Some(&demand), &plans, false, &labels, &None);
+                gather(&p_full, &full.0, &index, &mut probe);
+                gather(&q_full, &full.1, &index, &mut probe);
+                gather(&p_demanded, &demanded.0, &index, &mut probe);
+                gather(&q_demanded, &demanded.1, &index, &mut probe);
+                (cin, pin, qin, rin, sin, uin, din)
+            });
+
+            for fact in load3(0, &prefix, "c.txt") { c.insert(fact); }
+            for fact in load2(0, &prefix, "p.txt") { p.insert(fact); }
+            for fact in load3(0, &prefix, "q.txt") { q.insert(fact); }
+            for fact in load3(0, &prefix, "r.txt") { r.insert(fact); }
+            for fact in load2(0, &prefix, "s.txt") { s.insert(fact); }
+            for fact in load3(0, &prefix, "u.txt") { u.insert(fact); }
+            c.close(); p.close(); q.close(); r.close(); s.close(); u.close();
+
+            for &x in [0, 1, 5, 50].iter() { d.insert(x); }
+            d.advance_to(1); d.flush();
+            while probe.less_than(d.time()) { worker.step(); }
+            agree(&full, &demanded, &[0, 1, 5, 50]);
+
+            // Withdrawn nodes retract what only they needed; new nodes extend the magic set.
+            d.remove(0);
+            d.insert(7);
+            d.advance_to(2); d.flush();
+            while probe.less_than(d.time()) { worker.step(); }
+            agree(&full, &demanded, &[1, 5, 50, 7]);
+        });
+
+        ::std::fs::remove_dir_all(&directory).expect("failed to remove directory");
+    }
+}
@@ -0,0 +1,53 @@
+//! Generates synthetic inputs for the benchmark problems.
+//!
+//! Usage: `generate <problem> <directory> [--option value ...]`
+//!
+//! Options name fields of the problem's configuration, as in `generate galen galen-data
+//! --concepts 100000 --skew 1.2 --seed 7`. Files are written to `directory`, which is created
+//! if needed, and their sizes are reported.
+
+extern crate differential;
+
+use std::path::Path;
+
+use differential::generate::{crdt, doop, galen};
+
+fn main() {
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    assert!(args.len() >= 2, "usage: generate <problem> <directory> [--option value ...]");
+    let (problem, directory) = (&args[0], Path::new(&args[1]));
+
+    let mut options = Vec::new();
+    let mut rest = args[2..].iter();
+    while let Some(flag) = rest.next() {
+        assert!(flag.starts_with("--"), "expected an option, found {}", flag);
+        let value = rest.next().unwrap_or_else(|| panic!("{} requires an argument", flag));
+        options.push((flag.trim_start_matches("--"), value.as_str()));
+    }
+
+    std::fs::create_dir_all(directory).unwrap_or_else(|e| panic!("{}: {}", directory.display(), e));
+
+    let counts = match problem.as_str() {
+        "galen" => {
+            let mut config = galen::Config::default();
+            for &(flag, value) in options.iter() { config.set(flag, value).unwrap_or_else(|e| panic!("{}", e)); }
+            galen::generate(&config, directory)
+        },
+        "crdt" => {
+            let mut config = crdt::Config::default();
+            for &(flag, value) in options.iter() { config.set(flag, value).unwrap_or_else(|e| panic!("{}", e)); }
+            crdt::generate(&config, directory)
+        },
+        "doop" => {
+            let mut config = doop::Config::default();
+            for &(flag, value) in options.iter() { config.set(flag, value).unwrap_or_else(|e| panic!("{}", e)); }
+            doop::generate(&config, directory)
+        },
+        _ => panic!("unknown problem: {}; expected crdt, doop, or galen", problem),
+    };
+
+    for (name, count) in counts.unwrap_or_else(|e| panic!("{}: {}", directory.display(), e)) {
+        println!("{}\t{}", name, count);
+    }
+}
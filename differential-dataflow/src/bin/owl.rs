@@ -0,0 +1,39 @@
+//! Encodes an OWL2-EL ontology as the relations `galen` reads.
+//!
+//! Usage: `owl <ontology> <directory>`
+//!
+//! Ontologies ending in `.obo` are read as OBO flat files, and others as OWL2 functional syntax.
+//! The relations `c`, `p`, `q`, `r`, `s`, and `u` are written to `directory`, which is created
+//! if needed, with `symbols.txt` naming the number of each concept and role. Constructs that
+//! could not be encoded are reported with the number of axioms skipped for each.
+
+extern crate differential;
+
+use std::path::Path;
+
+use differential::owl::Encoding;
+
+fn main() {
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    assert!(args.len() == 2, "usage: owl <ontology> <directory>");
+    let (ontology, directory) = (Path::new(&args[0]), Path::new(&args[1]));
+
+    let text = std::fs::read_to_string(ontology).unwrap_or_else(|e| panic!("{}: {}", ontology.display(), e));
+    let mut encoding = Encoding::new();
+    let read = if ontology.extension().map_or(false, |extension| extension == "obo") {
+        encoding.read_obo(&text)
+    }
+    else {
+        encoding.read_functional(&text)
+    };
+    read.unwrap_or_else(|e| panic!("{}: {}", ontology.display(), e));
+
+    std::fs::create_dir_all(directory).unwrap_or_else(|e| panic!("{}: {}", directory.display(), e));
+    for (name, count) in encoding.write(directory).unwrap_or_else(|e| panic!("{}: {}", directory.display(), e)) {
+        println!("{}\t{}", name, count);
+    }
+    for (construct, count) in encoding.skipped().iter() {
+        eprintln!("skipped\t{}\t{}", construct, count);
+    }
+}
@@ -0,0 +1,278 @@
+//! Renders benchmark results as the README's evaluation tables.
+//!
+//! Usage: `tables [--readme ../README.md] results.json ...`
+//!
+//! Without `--readme` the tables are printed. With it, the Differential Dataflow rows of each
+//! problem's table are replaced by rows for the recorded runs, keeping other engines' rows and
+//! any compilation time already noted, and the environments are listed beneath the table.
+
+extern crate differential;
+
+use std::collections::BTreeMap;
+
+use differential::bench::{Environment, Results, Run};
+
+const ENGINE: &str = "Differential Dataflow";
+const HEADER: &str = "\
+| Engine                | Compilation   | Evaluation    | Cores | Notes     |
+|----------------------:|--------------:|--------------:|------:|----------:|";
+
+fn main() {
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut readme = None;
+    if let Some(position) = args.iter().position(|arg| arg == "--readme") {
+        args.remove(position);
+        assert!(position < args.len(), "--readme requires an argument");
+        readme = Some(args.remove(position));
+    }
+    assert!(!args.is_empty(), "no results to render; supply result files");
+
+    // Runs grouped by problem, then by environment and worker count.
+    let mut environments: Vec<Environment> = Vec::new();
+    let mut problems: BTreeMap<String, BTreeMap<(usize, usize), Vec<Run>>> = BTreeMap::new();
+    for path in args.iter() {
+        let results = Results::read(path).unwrap_or_else(|error| panic!("{}", error));
+        let index = environments.iter().position(|e| same_machine(e, &results.environment)).unwrap_or_else(|| {
+            environments.push(results.environment.clone());
+            environments.len() - 1
+        });
+        for run in results.runs {
+            problems.entry(run.problem.clone()).or_insert_with(BTreeMap::new).entry((index, run.workers)).or_insert_with(Vec::new).push(run);
+        }
+    }
+
+    match readme {
+        None => {
+            for (problem, configurations) in problems.iter() {
+                println!("#### The {} benchmark\n", problem.to_uppercase());
+                println!("{}", HEADER);
+                for row in rows(configurations, &BTreeMap::new()) {
+                    println!("{}", row);
+                }
+                println!();
+                for line in notes(configurations, &environments) {
+                    println!("{}", line);
+                }
+                println!();
+            }
+        },
+        Some(path) => {
+            let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            let mut lines = text.lines().map(|line| line.to_owned()).collect::<Vec<_>>();
+            for (problem, configurations) in problems.iter() {
+                if !update(&mut lines, problem, configurations, &environments) {
+                    eprintln!("no table for {} in {}; skipped", problem, path);
+                }
+            }
+            let mut text = lines.join("\n");
+            text.push('\n');
+            std::fs::write(&path, text).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        },
+    }
+}
+
+/// Replaces our rows and notes in the table for `problem`, returning false if there is no such table.
+fn update(lines: &mut Vec<String>, problem: &str, configurations: &BTreeMap<(usize, usize), Vec<Run>>, environments: &[Environment]) -> bool {
+
+    let heading = format!("#### The {} benchmark", problem.to_uppercase());
+    let start = match lines.iter().position(|line| line.trim() == heading) {
+        Some(start) => start,
+        None => return false,
+    };
+    let table = match lines[start..].iter().position(|line| line.starts_with('|')) {
+        Some(offset) => start + offset,
+        None => return false,
+    };
+    let end = lines[table..].iter().position(|line| !line.starts_with('|')).map(|offset| table + offset).unwrap_or(lines.len());
+
+    // Compilation times are not measured by `bench`, so keep any recorded by hand.
+    let mut compilation = BTreeMap::new();
+    for line in lines[table .. end].iter().filter(|line| is_ours(line)) {
+        let cells = line.split('|').map(|cell| cell.trim()).collect::<Vec<_>>();
+        if let (Some(time), Some(cores)) = (cells.get(2), cells.get(4).and_then(|c| c.parse::<usize>().ok())) {
+            if !time.is_empty() { compilation.insert(cores, time.to_string()); }
+        }
+    }
+
+    // Our rows go where the first of the old ones was, or else at the end of the table.
+    let position = lines[table .. end].iter().position(|line| is_ours(line)).map(|offset| table + offset).unwrap_or(end);
+    let others = lines[table .. end].iter().filter(|line| !is_ours(line)).cloned().collect::<Vec<_>>();
+    let before = others.iter().take(position - table).cloned().collect::<Vec<_>>();
+    let after = others.iter().skip(position - table).cloned().collect::<Vec<_>>();
+
+    let mut table_lines = before;
+    table_lines.extend(rows(configurations, &compilation));
+    table_lines.extend(after);
+
+    // Notes from a previous update directly follow the table, after one blank line.
+    let mut tail = end;
+    if tail + 1 < lines.len() && lines[tail].is_empty() && lines[tail + 1].starts_with(ENGINE) {
+        tail += 1;
+        while tail < lines.len() && lines[tail].starts_with(ENGINE) { tail += 1; }
+    }
+    let mut replacement = table_lines;
+    replacement.push(String::new());
+    replacement.extend(notes(configurations, environments));
+
+    lines.splice(table .. tail, replacement);
+    true
+}
+
+/// Whether a table row reports this engine.
+fn is_ours(line: &str) -> bool {
+    line.trim_start_matches('|').trim().starts_with(ENGINE)
+}
+
+/// Table rows for each configuration: the median fixpoint time over successful runs.
+fn rows(configurations: &BTreeMap<(usize, usize), Vec<Run>>, compilation: &BTreeMap<usize, String>) -> Vec<String> {
+    configurations.iter().map(|(&(environment, workers), runs)| {
+        let mut times = runs.iter().filter(|run| run.success).filter_map(|run| run.fixpoint).collect::<Vec<_>>();
+        times.sort_by(|a, b| a.partial_cmp(b).expect("time is not a number"));
+        let evaluation = if times.is_empty() { "failed".to_owned() } else { format!("{:.2}s", times[times.len() / 2]) };
+        let compile = compilation.get(&workers).cloned().unwrap_or_default();
+        format!("| {:<21} | {:<13} | {:<13} | {:>5} | {:<9} |", ENGINE, compile, evaluation, workers, format!("env {}", environment + 1))
+    }).collect()
+}
+
+/// Lines describing the environments and repetitions behind the rows.
+fn notes(configurations: &BTreeMap<(usize, usize), Vec<Run>>, environments: &[Environment]) -> Vec<String> {
+    let mut used = configurations.keys().map(|&(environment, _)| environment).collect::<Vec<_>>();
+    used.dedup();
+    used.into_iter().map(|index| {
+        let environment = &environments[index];
+        let runs = configurations.iter().filter(|((e, _), _)| *e == index).map(|(_, runs)| runs.len()).max().unwrap_or(0);
+        format!("{} env {}: {}, {} cores, {}, kernel {}, recorded {}; median of {} run(s).",
+            ENGINE, index + 1, environment.cpu, environment.cores, environment.rustc, environment.os, date(environment.timestamp), runs)
+    }).collect()
+}
+
+/// Whether two environments describe the same machine and toolchain.
+fn same_machine(a: &Environment, b: &Environment) -> bool {
+    a.host == b.host && a.cpu == b.cpu && a.cores == b.cores && a.rustc == b.rustc && a.os == b.os
+}
+
+/// The UTC date of a Unix timestamp, as `YYYY-MM-DD`.
+fn date(timestamp: u64) -> String {
+    // Days to civil date, after Howard Hinnant's algorithm.
+    let days = timestamp / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn environment() -> Environment {
+        Environment {
+            host: "laptop".to_owned(),
+            cpu: "Example CPU".to_owned(),
+            cores: 8,
+            os: "5.0.0".to_owned(),
+            rustc: "rustc 1.36.0".to_owned(),
+            timestamp: 1_559_347_200,
+        }
+    }
+
+    /// Successful runs on the first environment with `workers` workers, reaching fixpoint at each of `fixpoints`.
+    fn runs(workers: usize, fixpoints: &[f64]) -> Vec<Run> {
+        fixpoints.iter().enumerate().map(|(repetition, &fixpoint)| Run {
+            problem: "crdt".to_owned(),
+            input: "input".to_owned(),
+            workers,
+            repetition,
+            success: true,
+            load: None,
+            fixpoint: Some(fixpoint),
+            updates: Vec::new(),
+            wall: fixpoint,
+            peak_rss_kb: None,
+            outputs: BTreeMap::new(),
+        }).collect()
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(|line| line.to_owned()).collect()
+    }
+
+    const NOTE: &str = "Differential Dataflow env 1: Example CPU, 8 cores, rustc 1.36.0, kernel 5.0.0, recorded 2019-06-01; median of 3 run(s).";
+
+    #[test]
+    fn update_replaces_our_row() {
+        let mut readme = lines("\
+#### The CRDT benchmark
+
+| Engine                | Compilation   | Evaluation    | Cores | Notes     |
+|----------------------:|--------------:|--------------:|------:|----------:|
+| Soufflé (compiled)    | 10.15s        | 294.73s       |     1 | Laptop    |
+| Differential Dataflow | 1.00s         | 9.99s         |     1 | Laptop    |
+| IncA                  |               |               |       |           |
+
+#### The DOOP benchmark");
+        let mut configurations = BTreeMap::new();
+        configurations.insert((0, 1), runs(1, &[3.0, 1.0, 2.0]));
+        assert!(update(&mut readme, "crdt", &configurations, &[environment()]));
+        let expected = lines(&format!("\
+#### The CRDT benchmark
+
+| Engine                | Compilation   | Evaluation    | Cores | Notes     |
+|----------------------:|--------------:|--------------:|------:|----------:|
+| Soufflé (compiled)    | 10.15s        | 294.73s       |     1 | Laptop    |
+| Differential Dataflow | 1.00s         | 2.00s         |     1 | env 1     |
+| IncA                  |               |               |       |           |
+
+{}
+
+#### The DOOP benchmark", NOTE));
+        assert_eq!(readme, expected);
+
+        // Updating again replaces the notes rather than adding to them.
+        assert!(update(&mut readme, "crdt", &configurations, &[environment()]));
+        assert_eq!(readme, expected);
+    }
+
+    #[test]
+    fn update_adds_configurations() {
+        let mut readme = lines("\
+#### The CRDT benchmark
+
+| Engine                | Compilation   | Evaluation    | Cores | Notes     |
+|----------------------:|--------------:|--------------:|------:|----------:|
+| Soufflé (compiled)    | 10.15s        | 294.73s       |     1 | Laptop    |");
+        let mut configurations = BTreeMap::new();
+        configurations.insert((0, 1), runs(1, &[4.0, 5.0, 6.0]));
+        configurations.insert((0, 4), runs(4, &[1.5]));
+        assert!(update(&mut readme, "crdt", &configurations, &[environment()]));
+        let expected = lines(&format!("\
+#### The CRDT benchmark
+
+| Engine                | Compilation   | Evaluation    | Cores | Notes     |
+|----------------------:|--------------:|--------------:|------:|----------:|
+| Soufflé (compiled)    | 10.15s        | 294.73s       |     1 | Laptop    |
+| Differential Dataflow |               | 5.00s         |     1 | env 1     |
+| Differential Dataflow |               | 1.50s         |     4 | env 1     |
+
+{}", NOTE));
+        assert_eq!(readme, expected);
+        assert!(!update(&mut readme, "galen", &configurations, &[environment()]));
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(86_399), "1970-01-01");
+        assert_eq!(date(86_400), "1970-01-02");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_709_164_800), "2024-02-29");
+        assert_eq!(date(4_102_444_800), "2100-01-01");
+    }
+}
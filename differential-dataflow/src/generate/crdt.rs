@@ -0,0 +1,289 @@
+//! Concurrent editing traces across replicas, as the `insert.txt` and `remove.txt` `crdt` reads.
+//!
+//! Each replica keeps its own copy of the document, as a sequence of elements with tombstones,
+//! and edits it: mostly typing at its cursor, sometimes moving the cursor, and sometimes
+//! deleting one element or a long run of them. Elements are named by Lamport timestamps
+//! `(ctr, node)`, with the replica's number as `node`. Replicas exchange operations every
+//! `sync` operations; until then their edits are concurrent, and with probability `conflicts`
+//! an insert is placed where another replica last inserted, making concurrent siblings.
+//!
+//! Operations are written in the order they were made, which respects causality: an element
+//! is inserted before anything is inserted after it or it is removed.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+
+use crate::rga::Id;
+
+use super::{Rng, parse, write};
+
+/// Shape of a generated trace.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of replicas editing concurrently.
+    pub replicas: usize,
+    /// Number of operations, inserts and removes together.
+    pub operations: usize,
+    /// Fraction of operations that start a delete.
+    pub deletes: f64,
+    /// Fraction of deletes that remove a run of elements rather than one.
+    pub long: f64,
+    /// Average length of a long delete.
+    pub length: usize,
+    /// Probability that a replica moves its cursor before typing.
+    pub jumps: f64,
+    /// Probability that an insert goes where another replica last inserted.
+    pub conflicts: f64,
+    /// Operations between exchanges of operations among replicas.
+    pub sync: usize,
+    /// Seed for the random number generator.
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            replicas: 3,
+            operations: 100_000,
+            deletes: 0.15,
+            long: 0.05,
+            length: 20,
+            jumps: 0.02,
+            conflicts: 0.05,
+            sync: 50,
+            seed: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Sets the field named `flag` from `value`.
+    pub fn set(&mut self, flag: &str, value: &str) -> Result<(), String> {
+        match flag {
+            "replicas" => self.replicas = parse(flag, value)?,
+            "operations" => self.operations = parse(flag, value)?,
+            "deletes" => self.deletes = parse(flag, value)?,
+            "long" => self.long = parse(flag, value)?,
+            "length" => self.length = parse(flag, value)?,
+            "jumps" => self.jumps = parse(flag, value)?,
+            "conflicts" => self.conflicts = parse(flag, value)?,
+            "sync" => self.sync = parse(flag, value)?,
+            "seed" => self.seed = parse(flag, value)?,
+            _ => return Err(format!("unknown crdt option --{}", flag)),
+        }
+        Ok(())
+    }
+}
+
+/// An operation, as recorded in the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Inserts the first element as the child of the second.
+    Insert(Id, Id),
+    /// Removes an element.
+    Remove(Id),
+}
+
+/// One replica's copy of the document.
+struct Replica {
+    node: usize,
+    clock: usize,
+    /// Elements in document order, with whether each is still visible.
+    elements: Vec<(Id, bool)>,
+    /// Index of the element after which the replica types.
+    cursor: usize,
+    /// Number of operations of the shared log this replica has applied.
+    applied: usize,
+}
+
+impl Replica {
+
+    fn new(node: usize) -> Self {
+        Replica { node, clock: 0, elements: vec![((0, 0), true)], cursor: 0, applied: 0 }
+    }
+
+    /// The index of `id`, searching outwards from the cursor, where edits cluster.
+    fn find(&self, id: Id) -> usize {
+        let len = self.elements.len();
+        for distance in 0 .. len {
+            if self.cursor + distance < len && self.elements[self.cursor + distance].0 == id { return self.cursor + distance; }
+            if distance <= self.cursor && self.elements[self.cursor - distance].0 == id { return self.cursor - distance; }
+        }
+        panic!("replica {} has no element {:?}", self.node, id)
+    }
+
+    /// Applies an operation from another replica.
+    fn apply(&mut self, op: Op) {
+        match op {
+            Op::Insert(id, parent) => {
+                // Elements after the parent with greater ids were inserted there later, or descend
+                // from such elements, and come first.
+                let mut index = self.find(parent) + 1;
+                while index < self.elements.len() && self.elements[index].0 > id { index += 1; }
+                self.elements.insert(index, (id, true));
+                if index <= self.cursor { self.cursor += 1; }
+                self.clock = self.clock.max(id.0);
+            },
+            Op::Remove(id) => {
+                let index = self.find(id);
+                self.elements[index].1 = false;
+            },
+        }
+    }
+
+    /// Inserts a new element after the one at `index`, and moves the cursor to it.
+    fn insert(&mut self, index: usize) -> Op {
+        self.clock += 1;
+        let id = (self.clock, self.node);
+        let parent = self.elements[index].0;
+        self.elements.insert(index + 1, (id, true));
+        self.cursor = index + 1;
+        Op::Insert(id, parent)
+    }
+
+    /// The index of a visible element at or after `index`, excluding the head.
+    fn visible_from(&self, index: usize) -> Option<usize> {
+        (index.max(1) .. self.elements.len()).find(|&i| self.elements[i].1)
+    }
+}
+
+/// Generates the operations of a trace.
+pub fn trace(config: &Config) -> Vec<Op> {
+
+    assert!(config.replicas > 0, "crdt needs at least one replica");
+
+    let mut rng = Rng::new(config.seed);
+    let mut replicas = (0 .. config.replicas).map(Replica::new).collect::<Vec<_>>();
+    let mut log: Vec<(usize, Op)> = Vec::new();
+    let mut removed = HashSet::new();
+    // Where each replica last inserted, as a parent.
+    let mut last = vec![None; config.replicas];
+
+    let mut step = 0;
+    while log.len() < config.operations {
+
+        if step % config.sync.max(1) == 0 {
+            for replica in replicas.iter_mut() {
+                while replica.applied < log.len() {
+                    let (origin, op) = log[replica.applied];
+                    if origin != replica.node { replica.apply(op); }
+                    replica.applied += 1;
+                }
+            }
+        }
+
+        let who = rng.below(config.replicas);
+        let replica = &mut replicas[who];
+
+        // A replica with nothing visible to delete types instead, so traces cannot stall on an empty document.
+        if rng.chance(config.deletes) && replica.visible_from(0).is_some() {
+            let count = if rng.chance(config.long) { 1 + rng.below(2 * config.length) } else { 1 };
+            let start = if rng.chance(config.jumps) { rng.below(replica.elements.len()) } else { replica.cursor };
+            // Deletes past the last visible element wrap to the start of the document.
+            let mut index = if replica.visible_from(start).is_some() { start } else { 0 };
+            for _ in 0 .. count {
+                match replica.visible_from(index) {
+                    Some(found) => {
+                        replica.elements[found].1 = false;
+                        let id = replica.elements[found].0;
+                        // Another replica may have removed the element concurrently; record it once.
+                        if removed.insert(id) { log.push((who, Op::Remove(id))); }
+                        index = found + 1;
+                    },
+                    None => break,
+                }
+            }
+        }
+        else {
+            let others = (0 .. config.replicas).filter(|&r| r != who).filter_map(|r| last[r]).collect::<Vec<Id>>();
+            let index = if !others.is_empty() && rng.chance(config.conflicts) {
+                // Only a parent the replica has seen will do.
+                let parent = others[rng.below(others.len())];
+                replica.elements.iter().position(|&(id, _)| id == parent).unwrap_or(replica.cursor)
+            }
+            else if rng.chance(config.jumps) {
+                rng.below(replica.elements.len())
+            }
+            else {
+                replica.cursor
+            };
+            let op = replica.insert(index);
+            if let Op::Insert(_, parent) = op { last[who] = Some(parent); }
+            log.push((who, op));
+        }
+        step += 1;
+    }
+
+    log.into_iter().map(|(_, op)| op).collect()
+}
+
+/// Writes `insert.txt` and `remove.txt` to `directory`, returning the number of each.
+pub fn generate(config: &Config, directory: &Path) -> io::Result<Vec<(&'static str, usize)>> {
+    let ops = trace(config);
+    let inserts = ops.iter().filter_map(|op| match *op {
+        Op::Insert(id, parent) => Some(format!("{} {} {} {}", id.0, id.1, parent.0, parent.1)),
+        Op::Remove(_) => None,
+    });
+    let removes = ops.iter().filter_map(|op| match *op {
+        Op::Remove(id) => Some(format!("{} {}", id.0, id.1)),
+        Op::Insert(_, _) => None,
+    });
+    Ok(vec![
+        ("insert.txt", write(directory, "insert.txt", inserts)?),
+        ("remove.txt", write(directory, "remove.txt", removes)?),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// A short trace with frequent conflicts, under `seed`.
+    fn config(seed: u64) -> Config {
+        Config { operations: 5_000, conflicts: 0.3, sync: 10, seed, ..Config::default() }
+    }
+
+    /// Checks that `ops` respects causality, returning the nodes that inserted.
+    fn causal(ops: &[Op]) -> HashSet<usize> {
+        let mut inserted = HashSet::new();
+        inserted.insert((0, 0));
+        let mut removed = HashSet::new();
+        for op in ops.iter() {
+            match *op {
+                Op::Insert(id, parent) => {
+                    assert!(inserted.contains(&parent), "{:?} precedes the insert of its parent", op);
+                    assert!(inserted.insert(id), "{:?} is inserted twice", id);
+                },
+                Op::Remove(id) => {
+                    assert!(id != (0, 0) && inserted.contains(&id), "{:?} precedes the insert of its element", op);
+                    assert!(removed.insert(id), "{:?} is removed twice", id);
+                },
+            }
+        }
+        inserted.into_iter().filter(|&id| id != (0, 0)).map(|(_, node)| node).collect()
+    }
+
+    #[test]
+    fn traces_are_deterministic() {
+        assert_eq!(trace(&config(1)), trace(&config(1)));
+        assert_ne!(trace(&config(1)), trace(&config(2)));
+    }
+
+    #[test]
+    fn traces_are_causal() {
+        let ops = trace(&config(1));
+        assert_eq!(ops.len(), 5_000);
+        assert!(ops.iter().filter_map(|op| match *op { Op::Remove(id) => Some(id), _ => None }).count() > 0);
+        let nodes = causal(&ops);
+        assert_eq!(nodes.len(), 3, "only nodes {:?} inserted", nodes);
+    }
+
+    #[test]
+    fn deletes_only() {
+        let ops = trace(&Config { operations: 1_000, deletes: 1.0, ..config(1) });
+        assert_eq!(ops.len(), 1_000);
+        causal(&ops);
+    }
+}
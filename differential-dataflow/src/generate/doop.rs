@@ -0,0 +1,441 @@
+//! Synthetic Java programs, as the 31 fact files `doop` reads.
+//!
+//! Programs have a class hierarchy under `java.lang.Object`, interfaces with abstract methods,
+//! array types, and a main class whose `main` method allocates objects and calls methods.
+//! Method names come from a shared pool, each name with a fixed descriptor, so that classes
+//! override their ancestors' methods and virtual calls dispatch on the allocated type. Skew
+//! concentrates superclasses, call targets and field accesses on a few popular classes and
+//! methods. Statements are otherwise drawn at random and need not type check, which the
+//! analysis does not require.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::Path;
+
+use super::{Rng, Zipf, parse, write};
+
+/// The fact files, all of which are written even if empty.
+pub const FILES: [&str; 31] = [
+    "ClassType.facts", "ArrayType.facts", "InterfaceType.facts", "ApplicationClass.facts",
+    "ThisVar.facts", "NormalHeap.facts", "StringConstant.facts", "AssignHeapAllocation.facts",
+    "AssignLocal.facts", "AssignCast.facts", "Field.facts", "StaticMethodInvocation.facts",
+    "SpecialMethodInvocation.facts", "VirtualMethodInvocation.facts", "Method.facts",
+    "StoreInstanceField.facts", "LoadInstanceField.facts", "StoreStaticField.facts",
+    "LoadStaticField.facts", "StoreArrayIndex.facts", "LoadArrayIndex.facts", "Return.facts",
+    "DirectSuperclass.facts", "DirectSuperinterface.facts", "MainClass.facts",
+    "Method-Modifier.facts", "FormalParam.facts", "Var-Type.facts", "ComponentType.facts",
+    "AssignReturnValue.facts", "ActualParam.facts",
+];
+
+const OBJECT: &str = "java.lang.Object";
+const STRING: &str = "java.lang.String";
+const MAIN: &str = "gen.Main";
+
+/// Sizes and skew of a generated program.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of classes, besides the main class and library classes.
+    pub classes: usize,
+    /// Number of interfaces, besides library interfaces.
+    pub interfaces: usize,
+    /// Average number of methods per class, besides constructors and initializers.
+    pub methods: usize,
+    /// Average number of statements per method body.
+    pub statements: usize,
+    /// Number of fields per class.
+    pub fields: usize,
+    /// Number of distinct method names, which overriding methods share.
+    pub names: usize,
+    /// Zipf exponent for the classes, methods and fields that code refers to; zero is uniform.
+    pub skew: f64,
+    /// Seed for the random number generator.
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            classes: 100,
+            interfaces: 10,
+            methods: 5,
+            statements: 10,
+            fields: 3,
+            names: 50,
+            skew: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Sets the field named `flag` from `value`.
+    pub fn set(&mut self, flag: &str, value: &str) -> Result<(), String> {
+        match flag {
+            "classes" => self.classes = parse(flag, value)?,
+            "interfaces" => self.interfaces = parse(flag, value)?,
+            "methods" => self.methods = parse(flag, value)?,
+            "statements" => self.statements = parse(flag, value)?,
+            "fields" => self.fields = parse(flag, value)?,
+            "names" => self.names = parse(flag, value)?,
+            "skew" => self.skew = parse(flag, value)?,
+            "seed" => self.seed = parse(flag, value)?,
+            _ => return Err(format!("unknown doop option --{}", flag)),
+        }
+        Ok(())
+    }
+}
+
+/// A method declaration.
+struct Method {
+    signature: String,
+    params: Vec<String>,
+    returns: Option<String>,
+    is_static: bool,
+}
+
+/// A pooled method name with its descriptor.
+struct Name {
+    name: String,
+    params: Vec<String>,
+    returns: Option<String>,
+}
+
+/// Facts accumulated for each file.
+struct Facts {
+    files: BTreeMap<&'static str, BTreeSet<String>>,
+}
+
+impl Facts {
+    fn add(&mut self, file: &'static str, fields: &[&str]) {
+        self.files.get_mut(file).unwrap_or_else(|| panic!("unknown fact file {}", file)).insert(fields.join("\t"));
+    }
+}
+
+/// Writes the fact files to `directory`, returning each file name and its number of facts.
+pub fn generate(config: &Config, directory: &Path) -> io::Result<Vec<(&'static str, usize)>> {
+
+    assert!(config.classes > 0 && config.names > 0, "doop needs at least one class and one method name");
+
+    let mut rng = Rng::new(config.seed);
+    let mut facts = Facts { files: FILES.iter().map(|&file| (file, BTreeSet::new())).collect() };
+
+    // Types, and the hierarchy among them.
+    let classes = (0 .. config.classes).map(|i| format!("gen.C{}", i)).collect::<Vec<_>>();
+    let interfaces = (0 .. config.interfaces).map(|i| format!("gen.I{}", i)).collect::<Vec<_>>();
+    let popular_class = Zipf::new(classes.len(), config.skew);
+
+    for class in [OBJECT, STRING, MAIN].iter() { facts.add("ClassType.facts", &[class]); }
+    for interface in ["java.lang.Cloneable", "java.io.Serializable"].iter() { facts.add("InterfaceType.facts", &[interface]); }
+    facts.add("DirectSuperclass.facts", &[STRING, OBJECT]);
+    facts.add("DirectSuperclass.facts", &[MAIN, OBJECT]);
+    facts.add("DirectSuperinterface.facts", &[STRING, "java.io.Serializable"]);
+    facts.add("ApplicationClass.facts", &[MAIN]);
+    facts.add("MainClass.facts", &[MAIN]);
+
+    for (index, class) in classes.iter().enumerate() {
+        facts.add("ClassType.facts", &[class]);
+        facts.add("ApplicationClass.facts", &[class]);
+        let superclass = if index == 0 || rng.chance(0.1) { OBJECT } else { &classes[popular_class.sample(&mut rng) % index] };
+        facts.add("DirectSuperclass.facts", &[class, superclass]);
+        if !interfaces.is_empty() && rng.chance(0.3) {
+            facts.add("DirectSuperinterface.facts", &[class, &interfaces[rng.below(interfaces.len())]]);
+        }
+    }
+    for (index, interface) in interfaces.iter().enumerate() {
+        facts.add("InterfaceType.facts", &[interface]);
+        facts.add("ApplicationClass.facts", &[interface]);
+        if index > 0 && rng.chance(0.5) {
+            facts.add("DirectSuperinterface.facts", &[interface, &interfaces[rng.below(index)]]);
+        }
+    }
+
+    // Arrays of library types and of some classes; the main method needs `java.lang.String[]`.
+    let mut arrays = vec![format!("{}[]", STRING), format!("{}[]", OBJECT)];
+    arrays.extend(classes.iter().filter(|_| rng.chance(0.2)).map(|class| format!("{}[]", class)));
+    for array in arrays.iter() {
+        facts.add("ArrayType.facts", &[array]);
+        facts.add("ComponentType.facts", &[array, &array[.. array.len() - 2]]);
+    }
+
+    // Pooled method names, each with a fixed descriptor.
+    let names = (0 .. config.names).map(|i| Name {
+        name: format!("m{}", i),
+        params: (0 .. i % 3).map(|_| classes[rng.below(classes.len())].clone()).collect(),
+        returns: if rng.chance(0.3) { None } else { Some(classes[rng.below(classes.len())].clone()) },
+    }).collect::<Vec<_>>();
+    let popular_name = Zipf::new(names.len(), config.skew);
+
+    // Method declarations: constructors, initializers, and methods with pooled names.
+    let mut methods = Vec::new();
+    let mut bodies = Vec::new();
+    for class in classes.iter() {
+        bodies.push(declare(&mut facts, &mut methods, class, "<init>", &[], None, &["public"]));
+        if rng.chance(0.2) {
+            bodies.push(declare(&mut facts, &mut methods, class, "<clinit>", &[], None, &["static"]));
+        }
+        let mut declared = BTreeSet::new();
+        for _ in 0 .. rng.below(2 * config.methods + 1) {
+            let index = popular_name.sample(&mut rng);
+            if declared.insert(index) {
+                let name = &names[index];
+                let modifiers: &[&str] = if rng.chance(0.2) { &["public", "static"] } else { &["public"] };
+                bodies.push(declare(&mut facts, &mut methods, class, &name.name, &name.params, name.returns.as_ref(), modifiers));
+            }
+        }
+    }
+    for interface in interfaces.iter() {
+        let mut declared = BTreeSet::new();
+        for _ in 0 .. rng.below(config.methods + 1) {
+            let index = popular_name.sample(&mut rng);
+            if declared.insert(index) {
+                let name = &names[index];
+                declare(&mut facts, &mut methods, interface, &name.name, &name.params, name.returns.as_ref(), &["public", "abstract"]);
+            }
+        }
+    }
+    let main = declare(&mut facts, &mut methods, MAIN, "main", &[format!("{}[]", STRING)], None, &["public", "static"]);
+
+    let fields = classes.iter().flat_map(|class| {
+        (0 .. config.fields).map(move |i| (class.clone(), i))
+    }).map(|(class, i)| {
+        let kind = &classes[rng.below(classes.len())];
+        let field = format!("<{}: {} f{}>", class, kind, i);
+        facts.add("Field.facts", &[&field, &class, &format!("f{}", i), kind]);
+        field
+    }).collect::<Vec<_>>();
+
+    // Method bodies, with the main method's larger than the rest.
+    let instance = methods.iter().enumerate().filter(|(_, method)| !method.is_static && !method.signature.contains("<init>")).map(|(i, _)| i).collect::<Vec<_>>();
+    let statics = methods.iter().enumerate().filter(|(_, method)| method.is_static && !method.signature.contains("<clinit>") && !method.signature.contains(" main(")).map(|(i, _)| i).collect::<Vec<_>>();
+    let context = Context {
+        classes: &classes,
+        arrays: &arrays,
+        fields: &fields,
+        methods: &methods,
+        instance: &instance,
+        statics: &statics,
+        popular_class,
+        popular_field: Zipf::new(fields.len().max(1), config.skew),
+        popular_instance: Zipf::new(instance.len().max(1), config.skew),
+        popular_static: Zipf::new(statics.len().max(1), config.skew),
+    };
+    context.body(&mut facts, &mut rng, main, 4 * config.statements + 1);
+    for method in bodies {
+        let statements = rng.below(2 * config.statements + 1);
+        context.body(&mut facts, &mut rng, method, statements);
+    }
+
+    let mut counts = Vec::new();
+    for &file in FILES.iter() {
+        let rows = ::std::mem::replace(facts.files.get_mut(file).expect("missing file"), BTreeSet::new());
+        counts.push((file, write(directory, file, rows)?));
+    }
+    Ok(counts)
+}
+
+/// Declares a method of `class`, returning its index in `methods`.
+fn declare(facts: &mut Facts, methods: &mut Vec<Method>, class: &str, name: &str, params: &[String], returns: Option<&String>, modifiers: &[&str]) -> usize {
+    let result = returns.map(|r| r.as_str()).unwrap_or("void");
+    let params_text = format!("({})", params.join(","));
+    let signature = format!("<{}: {} {}{}>", class, result, name, params_text);
+    let jvm = format!("({}){}", params.iter().map(|p| jvm_type(p)).collect::<String>(), if returns.is_some() { jvm_type(result) } else { "V".to_owned() });
+    facts.add("Method.facts", &[&signature, name, &params_text, class, result, &jvm, &params.len().to_string()]);
+    for modifier in modifiers.iter() {
+        facts.add("Method-Modifier.facts", &[modifier, &signature]);
+    }
+    methods.push(Method {
+        signature,
+        params: params.to_vec(),
+        returns: returns.cloned(),
+        is_static: modifiers.contains(&"static"),
+    });
+    methods.len() - 1
+}
+
+/// The JVM descriptor of a reference type.
+fn jvm_type(name: &str) -> String {
+    if name.ends_with("[]") { format!("[{}", jvm_type(&name[.. name.len() - 2])) }
+    else { format!("L{};", name.replace('.', "/")) }
+}
+
+/// What method bodies may refer to.
+struct Context<'a> {
+    classes: &'a [String],
+    arrays: &'a [String],
+    fields: &'a [String],
+    methods: &'a [Method],
+    instance: &'a [usize],
+    statics: &'a [usize],
+    popular_class: Zipf,
+    popular_field: Zipf,
+    popular_instance: Zipf,
+    popular_static: Zipf,
+}
+
+impl<'a> Context<'a> {
+
+    /// Writes a body of `statements` statements for `methods[index]`.
+    fn body(&self, facts: &mut Facts, rng: &mut Rng, index: usize, statements: usize) {
+
+        let method = &self.methods[index];
+        let sig = method.signature.as_str();
+
+        // Variables: the receiver, parameters, and locals.
+        let mut vars = Vec::new();
+        if !method.is_static {
+            let this = format!("{}/@this", sig);
+            facts.add("ThisVar.facts", &[sig, &this]);
+            facts.add("Var-Type.facts", &[&this, &sig[1 .. sig.find(':').expect("malformed signature")]]);
+            vars.push(this);
+        }
+        for (position, kind) in method.params.iter().enumerate() {
+            let param = format!("{}/@param{}", sig, position);
+            facts.add("FormalParam.facts", &[&position.to_string(), sig, &param]);
+            facts.add("Var-Type.facts", &[&param, kind]);
+            vars.push(param);
+        }
+        for local in 0 .. statements / 2 + 1 {
+            let var = format!("{}/v{}", sig, local);
+            facts.add("Var-Type.facts", &[&var, self.class(rng)]);
+            vars.push(var);
+        }
+        let var = |rng: &mut Rng| vars[rng.below(vars.len())].clone();
+
+        for n in 0 .. statements {
+            let index = n.to_string();
+            let index = index.as_str();
+            match rng.below(100) {
+                0 ..= 19 => {
+                    let insn = format!("{}/new/{}", sig, n);
+                    let to = var(rng);
+                    let kind = if rng.chance(0.1) { self.arrays[rng.below(self.arrays.len())].as_str() } else { self.class(rng) };
+                    let heap = format!("{}/new {}/{}", sig, kind, n);
+                    facts.add("NormalHeap.facts", &[&heap, kind]);
+                    facts.add("AssignHeapAllocation.facts", &[&insn, index, &heap, &to, sig, index]);
+                    if !kind.ends_with("[]") {
+                        let insn = format!("{}/invoke-special/{}", sig, n);
+                        let init = format!("<{}: void <init>()>", kind);
+                        facts.add("SpecialMethodInvocation.facts", &[&insn, index, &init, &to, sig]);
+                    }
+                },
+                20 ..= 24 => {
+                    let insn = format!("{}/string/{}", sig, n);
+                    let constant = format!("\"s{}\"", rng.below(100));
+                    facts.add("StringConstant.facts", &[&constant]);
+                    facts.add("AssignHeapAllocation.facts", &[&insn, index, &constant, &var(rng), sig, "0"]);
+                },
+                25 ..= 39 => {
+                    let insn = format!("{}/assign/{}", sig, n);
+                    facts.add("AssignLocal.facts", &[&insn, index, &var(rng), &var(rng), sig]);
+                },
+                40 ..= 44 => {
+                    let insn = format!("{}/cast/{}", sig, n);
+                    facts.add("AssignCast.facts", &[&insn, index, &var(rng), &var(rng), self.class(rng), sig]);
+                },
+                45 ..= 52 => {
+                    let insn = format!("{}/store-field/{}", sig, n);
+                    facts.add("StoreInstanceField.facts", &[&insn, index, &var(rng), &var(rng), self.field(rng), sig]);
+                },
+                53 ..= 60 => {
+                    let insn = format!("{}/load-field/{}", sig, n);
+                    facts.add("LoadInstanceField.facts", &[&insn, index, &var(rng), &var(rng), self.field(rng), sig]);
+                },
+                61 ..= 63 => {
+                    let insn = format!("{}/store-static/{}", sig, n);
+                    facts.add("StoreStaticField.facts", &[&insn, index, &var(rng), self.field(rng), sig]);
+                },
+                64 ..= 66 => {
+                    let insn = format!("{}/load-static/{}", sig, n);
+                    facts.add("LoadStaticField.facts", &[&insn, index, &var(rng), self.field(rng), sig]);
+                },
+                67 ..= 69 => {
+                    let insn = format!("{}/store-array/{}", sig, n);
+                    facts.add("StoreArrayIndex.facts", &[&insn, index, &var(rng), &var(rng), sig]);
+                },
+                70 ..= 72 => {
+                    let insn = format!("{}/load-array/{}", sig, n);
+                    facts.add("LoadArrayIndex.facts", &[&insn, index, &var(rng), &var(rng), sig]);
+                },
+                73 ..= 89 if !self.instance.is_empty() => {
+                    let target = &self.methods[self.instance[self.popular_instance.sample(rng) % self.instance.len()]];
+                    let insn = format!("{}/invoke-virtual/{}", sig, n);
+                    facts.add("VirtualMethodInvocation.facts", &[&insn, index, &target.signature, &var(rng), sig]);
+                    self.call(facts, rng, &insn, target, &var);
+                },
+                90 ..= 94 if !self.statics.is_empty() => {
+                    let target = &self.methods[self.statics[self.popular_static.sample(rng) % self.statics.len()]];
+                    let insn = format!("{}/invoke-static/{}", sig, n);
+                    facts.add("StaticMethodInvocation.facts", &[&insn, index, &target.signature, sig]);
+                    self.call(facts, rng, &insn, target, &var);
+                },
+                _ => {
+                    let insn = format!("{}/assign/{}", sig, n);
+                    facts.add("AssignLocal.facts", &[&insn, index, &var(rng), &var(rng), sig]);
+                },
+            }
+        }
+
+        if method.returns.is_some() {
+            let insn = format!("{}/return/{}", sig, statements);
+            facts.add("Return.facts", &[&insn, &statements.to_string(), &var(rng), sig]);
+        }
+    }
+
+    /// Passes arguments to and collects the result of the invocation `insn` of `target`.
+    fn call<F: Fn(&mut Rng)->String>(&self, facts: &mut Facts, rng: &mut Rng, insn: &str, target: &Method, var: &F) {
+        for position in 0 .. target.params.len() {
+            facts.add("ActualParam.facts", &[&position.to_string(), insn, &var(rng)]);
+        }
+        if target.returns.is_some() {
+            facts.add("AssignReturnValue.facts", &[insn, &var(rng)]);
+        }
+    }
+
+    fn class(&self, rng: &mut Rng) -> &'a str {
+        &self.classes[self.popular_class.sample(rng)]
+    }
+
+    fn field(&self, rng: &mut Rng) -> &'a str {
+        if self.fields.is_empty() { "<java.lang.Object: java.lang.Object none>" }
+        else { &self.fields[self.popular_field.sample(rng)] }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::scratch;
+
+    /// The number of fields `doop` loads from each file, as in its `load1` through `load7` calls.
+    const ARITIES: [(&str, usize); 31] = [
+        ("ClassType.facts", 1), ("ArrayType.facts", 1), ("InterfaceType.facts", 1), ("ApplicationClass.facts", 1),
+        ("ThisVar.facts", 2), ("NormalHeap.facts", 2), ("StringConstant.facts", 1), ("AssignHeapAllocation.facts", 6),
+        ("AssignLocal.facts", 5), ("AssignCast.facts", 6), ("Field.facts", 4), ("StaticMethodInvocation.facts", 4),
+        ("SpecialMethodInvocation.facts", 5), ("VirtualMethodInvocation.facts", 5), ("Method.facts", 7),
+        ("StoreInstanceField.facts", 6), ("LoadInstanceField.facts", 6), ("StoreStaticField.facts", 5),
+        ("LoadStaticField.facts", 5), ("StoreArrayIndex.facts", 5), ("LoadArrayIndex.facts", 5), ("Return.facts", 4),
+        ("DirectSuperclass.facts", 2), ("DirectSuperinterface.facts", 2), ("MainClass.facts", 1),
+        ("Method-Modifier.facts", 2), ("FormalParam.facts", 3), ("Var-Type.facts", 2), ("ComponentType.facts", 2),
+        ("AssignReturnValue.facts", 2), ("ActualParam.facts", 3),
+    ];
+
+    #[test]
+    fn rows_match_loaders() {
+        assert_eq!(ARITIES.iter().map(|&(file, _)| file).collect::<Vec<_>>(), FILES.to_vec());
+        let config = Config { classes: 30, seed: 3, ..Config::default() };
+        let directory = scratch("doop");
+        let counts = generate(&config, &directory).unwrap();
+        for &(file, arity) in ARITIES.iter() {
+            let text = std::fs::read_to_string(directory.join(file)).unwrap();
+            assert_eq!(counts.iter().find(|&&(name, _)| name == file).unwrap().1, text.lines().count());
+            assert!(text.lines().count() > 0, "{} is empty", file);
+            for line in text.lines() {
+                let fields = line.split('\t').collect::<Vec<_>>();
+                assert_eq!(fields.len(), arity, "{}: {}", file, line);
+                assert!(fields.iter().all(|field| !field.is_empty()), "{}: {}", file, line);
+            }
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+//! GALEN-like ontologies, as the six relations `galen` reads.
+//!
+//! Concepts are numbered from zero and roles follow them, so the two never collide. Skew is
+//! applied to the concepts and roles that facts refer to, with low numbers the most popular,
+//! as in real ontologies where a few general concepts (body part, process) are used throughout.
+//! Subsumptions `p` and `s` always point from a higher number to a lower one, so the hierarchy
+//! is acyclic and its closure stays proportionate to its size.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::Path;
+
+use super::{Rng, Zipf, parse, write};
+
+/// Sizes and skew of a generated ontology.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Number of concepts.
+    pub concepts: usize,
+    /// Number of roles.
+    pub roles: usize,
+    /// Facts `p(x,y)`: concept `x` is subsumed by `y`.
+    pub p: usize,
+    /// Facts `q(x,r,y)`: concept `x` is subsumed by `∃r.y`.
+    pub q: usize,
+    /// Facts `c(x,y,z)`: the intersection of `x` and `y` is subsumed by `z`.
+    pub c: usize,
+    /// Facts `u(x,r,y)`: `∃r.x` is subsumed by `y`.
+    pub u: usize,
+    /// Facts `s(r,t)`: role `r` is subsumed by `t`.
+    pub s: usize,
+    /// Facts `r(r,t,v)`: the chain of `r` then `t` is subsumed by `v`.
+    pub r: usize,
+    /// Zipf exponent for the concepts and roles facts refer to; zero is uniform.
+    pub skew: f64,
+    /// Seed for the random number generator.
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::scaled(10_000)
+    }
+}
+
+impl Config {
+    /// Relation sizes in roughly the proportions of GALEN, for `concepts` concepts.
+    pub fn scaled(concepts: usize) -> Self {
+        let roles = (concepts / 100).max(2);
+        Config {
+            concepts,
+            roles,
+            p: concepts * 2,
+            q: concepts,
+            c: concepts / 10,
+            u: concepts / 10,
+            s: roles,
+            r: roles / 4,
+            skew: 1.0,
+            seed: 0,
+        }
+    }
+
+    /// Sets the field named `flag` from `value`.
+    ///
+    /// Setting `concepts` rescales all sizes, so it should come before any other size.
+    pub fn set(&mut self, flag: &str, value: &str) -> Result<(), String> {
+        match flag {
+            "concepts" => *self = Config { skew: self.skew, seed: self.seed, ..Config::scaled(parse(flag, value)?) },
+            "roles" => self.roles = parse(flag, value)?,
+            "p" => self.p = parse(flag, value)?,
+            "q" => self.q = parse(flag, value)?,
+            "c" => self.c = parse(flag, value)?,
+            "u" => self.u = parse(flag, value)?,
+            "s" => self.s = parse(flag, value)?,
+            "r" => self.r = parse(flag, value)?,
+            "skew" => self.skew = parse(flag, value)?,
+            "seed" => self.seed = parse(flag, value)?,
+            _ => return Err(format!("unknown galen option --{}", flag)),
+        }
+        Ok(())
+    }
+}
+
+/// Writes `c.txt`, `p.txt`, `q.txt`, `r.txt`, `s.txt`, and `u.txt` to `directory`.
+///
+/// Returns the file names and the number of facts in each, which may fall short of the
+/// configured sizes when there are too few distinct facts to draw.
+pub fn generate(config: &Config, directory: &Path) -> io::Result<Vec<(&'static str, usize)>> {
+
+    assert!(config.concepts > 1 && config.roles > 1, "galen needs at least two concepts and two roles");
+
+    let mut rng = Rng::new(config.seed);
+    let concept = Zipf::new(config.concepts, config.skew);
+    let role = Zipf::new(config.roles, config.skew);
+    let offset = config.concepts;
+
+    // Subsumptions pair an arbitrary node with a popular one, oriented towards the lower number.
+    let p = distinct(config.p, &mut rng, |rng| {
+        let (x, y) = (rng.below(config.concepts), concept.sample(rng));
+        if x == y { None } else { Some(vec![x.max(y), x.min(y)]) }
+    });
+    let s = distinct(config.s, &mut rng, |rng| {
+        let (x, y) = (rng.below(config.roles), role.sample(rng));
+        if x == y { None } else { Some(vec![offset + x.max(y), offset + x.min(y)]) }
+    });
+    let q = distinct(config.q, &mut rng, |rng| {
+        Some(vec![rng.below(config.concepts), offset + role.sample(rng), concept.sample(rng)])
+    });
+    let c = distinct(config.c, &mut rng, |rng| {
+        let (x, y) = (concept.sample(rng), concept.sample(rng));
+        if x == y { None } else { Some(vec![x, y, rng.below(config.concepts)]) }
+    });
+    let u = distinct(config.u, &mut rng, |rng| {
+        Some(vec![concept.sample(rng), offset + role.sample(rng), concept.sample(rng)])
+    });
+    let r = distinct(config.r, &mut rng, |rng| {
+        Some(vec![offset + role.sample(rng), offset + role.sample(rng), offset + role.sample(rng)])
+    });
+
+    let mut counts = Vec::new();
+    for (name, facts) in vec![("c.txt", c), ("p.txt", p), ("q.txt", q), ("r.txt", r), ("s.txt", s), ("u.txt", u)] {
+        let rows = facts.into_iter().map(|fact| fact.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","));
+        counts.push((name, write(directory, name, rows)?));
+    }
+    Ok(counts)
+}
+
+/// Draws up to `count` distinct facts from `draw`, giving up after many fruitless attempts.
+fn distinct<F: FnMut(&mut Rng)->Option<Vec<usize>>>(count: usize, rng: &mut Rng, mut draw: F) -> BTreeSet<Vec<usize>> {
+    let mut facts = BTreeSet::new();
+    let mut misses = 0;
+    while facts.len() < count && misses < 100 * count + 1000 {
+        let inserted = draw(rng).map_or(false, |fact| facts.insert(fact));
+        if !inserted { misses += 1; }
+    }
+    facts
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::scratch;
+
+    /// The facts in `directory/name`, as numbers.
+    fn read(directory: &Path, name: &str) -> Vec<Vec<usize>> {
+        std::fs::read_to_string(directory.join(name)).unwrap()
+            .lines()
+            .map(|line| line.split(',').map(|x| x.parse().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn relations_are_well_formed() {
+        let config = Config { seed: 5, ..Config::scaled(1_000) };
+        let directory = scratch("galen");
+        let counts = generate(&config, &directory).unwrap();
+
+        let concept = |x: usize| x < config.concepts;
+        let role = |x: usize| x >= config.concepts && x < config.concepts + config.roles;
+        let sizes = [("c.txt", config.c), ("p.txt", config.p), ("q.txt", config.q), ("r.txt", config.r), ("s.txt", config.s), ("u.txt", config.u)];
+        for &(name, size) in sizes.iter() {
+            let facts = read(&directory, name);
+            assert_eq!(counts.iter().find(|&&(file, _)| file == name).unwrap().1, facts.len());
+            assert!(facts.len() <= size);
+            let kinds: &[&dyn Fn(usize)->bool] = match name {
+                "c.txt" => &[&concept, &concept, &concept],
+                "p.txt" => &[&concept, &concept],
+                "q.txt" | "u.txt" => &[&concept, &role, &concept],
+                "r.txt" => &[&role, &role, &role],
+                "s.txt" => &[&role, &role],
+                _ => unreachable!(),
+            };
+            for fact in facts.iter() {
+                assert_eq!(fact.len(), kinds.len(), "{}: {:?}", name, fact);
+                assert!(fact.iter().zip(kinds.iter()).all(|(&x, kind)| kind(x)), "{}: {:?}", name, fact);
+                match name {
+                    // Subsumptions point to a lower number, so the hierarchy is acyclic.
+                    "p.txt" | "s.txt" => assert!(fact[0] > fact[1], "{}: {:?}", name, fact),
+                    "c.txt" => assert_ne!(fact[0], fact[1]),
+                    _ => { },
+                }
+            }
+        }
+        // Most facts are found even at this small scale.
+        assert!(read(&directory, "p.txt").len() > config.p / 2);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let config = Config { seed: 11, ..Config::scaled(200) };
+        let (first, second) = (scratch("galen-first"), scratch("galen-second"));
+        generate(&config, &first).unwrap();
+        generate(&config, &second).unwrap();
+        for name in ["c.txt", "p.txt", "q.txt", "r.txt", "s.txt", "u.txt"].iter() {
+            assert_eq!(read(&first, name), read(&second, name), "{} differs", name);
+        }
+        std::fs::remove_dir_all(&first).unwrap();
+        std::fs::remove_dir_all(&second).unwrap();
+    }
+}
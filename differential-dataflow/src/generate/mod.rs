@@ -0,0 +1,163 @@
+//! Synthetic inputs for the benchmark problems, at configurable scale and skew.
+//!
+//! Each problem has a `Config` whose fields are set from `--field value` flags by `set`, and a
+//! `generate` function writing input files to a directory in the format its binary reads.
+//! Generation is deterministic given the configuration, including its seed.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub mod crdt;
+pub mod doop;
+pub mod galen;
+
+/// A xorshift64* generator: fast, small, and reproducible across platforms.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a generator from `seed`; any seed, including zero, is acceptable.
+    pub fn new(seed: u64) -> Self {
+        // Spread the seed with splitmix64, as the state must not be zero.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng { state: if z == 0 { 1 } else { z } }
+    }
+
+    /// The next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A uniform value in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A uniform value in `[0, bound)`; `bound` must be positive.
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_f64() * bound as f64) as usize
+    }
+
+    /// True with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        self.next_f64() < p
+    }
+}
+
+/// Draws ranks in `[0, n)` with probability proportional to `1 / (rank + 1)^exponent`.
+///
+/// An exponent of zero is uniform; larger exponents concentrate draws on the lowest ranks.
+pub struct Zipf {
+    cumulative: Vec<f64>,
+}
+
+impl Zipf {
+    /// Prepares to draw from `n` ranks, which must be positive.
+    pub fn new(n: usize, exponent: f64) -> Self {
+        assert!(n > 0, "cannot draw from no ranks");
+        let mut cumulative = Vec::with_capacity(n);
+        let mut total = 0.0;
+        for rank in 0 .. n {
+            total += 1.0 / ((rank + 1) as f64).powf(exponent);
+            cumulative.push(total);
+        }
+        for weight in cumulative.iter_mut() { *weight /= total; }
+        Zipf { cumulative }
+    }
+
+    /// Draws a rank.
+    pub fn sample(&self, rng: &mut Rng) -> usize {
+        let target = rng.next_f64();
+        let rank = match self.cumulative.binary_search_by(|weight| weight.partial_cmp(&target).expect("weight is not a number")) {
+            Ok(rank) => rank + 1,
+            Err(rank) => rank,
+        };
+        rank.min(self.cumulative.len() - 1)
+    }
+}
+
+/// Parses `value` for the flag `--flag`.
+pub fn parse<T: ::std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("malformed value for --{}: {}", flag, value))
+}
+
+/// Writes `rows` to `directory/name`, one per line, returning how many were written.
+pub fn write<I: IntoIterator<Item=String>>(directory: &Path, name: &str, rows: I) -> io::Result<usize> {
+    let mut file = BufWriter::new(File::create(directory.join(name))?);
+    let mut count = 0;
+    for row in rows {
+        writeln!(file, "{}", row)?;
+        count += 1;
+    }
+    file.flush()?;
+    Ok(count)
+}
+
+/// A fresh directory for the output of a generator under test.
+#[cfg(test)]
+fn scratch(name: &str) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!("generate-{}-{}", name, std::process::id()));
+    if directory.exists() { std::fs::remove_dir_all(&directory).unwrap(); }
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn rng_is_deterministic() {
+        let draws = |seed| { let mut rng = Rng::new(seed); (0 .. 100).map(|_| rng.next_u64()).collect::<Vec<_>>() };
+        assert_eq!(draws(0), draws(0));
+        assert_eq!(draws(7), draws(7));
+        assert_ne!(draws(0), draws(1));
+        assert!(draws(0).iter().any(|&x| x != 0));
+    }
+
+    #[test]
+    fn rng_stays_in_range() {
+        let mut rng = Rng::new(3);
+        for _ in 0 .. 10_000 {
+            let x = rng.next_f64();
+            assert!((0.0 .. 1.0).contains(&x));
+            assert_eq!(rng.below(1), 0);
+            assert!(rng.below(7) < 7);
+        }
+        assert!(!rng.chance(0.0));
+        assert!(rng.chance(1.0));
+    }
+
+    #[test]
+    fn zipf_stays_in_range() {
+        let mut rng = Rng::new(0);
+        for &n in [1, 2, 10, 1000].iter() {
+            for &exponent in [0.0, 1.0, 3.0].iter() {
+                let zipf = Zipf::new(n, exponent);
+                for _ in 0 .. 10_000 {
+                    assert!(zipf.sample(&mut rng) < n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zipf_favours_low_ranks() {
+        let mut rng = Rng::new(0);
+        let zipf = Zipf::new(10, 2.0);
+        let mut counts = vec![0; 10];
+        for _ in 0 .. 10_000 { counts[zipf.sample(&mut rng)] += 1; }
+        // Rank 0 has weight 1 of about 1.55 in total.
+        assert!(counts[0] > 6_000 && counts[0] < 7_000, "{:?}", counts);
+        assert!(counts[0] > counts[1] && counts[1] > counts[9]);
+    }
+}
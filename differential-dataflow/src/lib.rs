@@ -1 +1,55 @@
-// nothing here
\ No newline at end of file
+extern crate timely;
+extern crate differential_dataflow;
+extern crate serde;
+extern crate serde_json;
+
+use std::collections::HashMap;
+
+pub mod bench;
+pub mod generate;
+pub mod owl;
+pub mod report;
+pub mod rga;
+pub mod server;
+pub mod shell;
+
+/// Symbols are interned strings, or plain numbers for problems without strings.
+pub type Symbol = u32;
+
+/// Maps strings to dense symbols and back.
+#[derive(Default)]
+pub struct StringInterner {
+    vec: Vec<String>,
+    map: HashMap<String, Symbol>
+}
+
+impl StringInterner {
+    pub fn new() -> Self { StringInterner { vec: Vec::new(), map: HashMap::new() } }
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if !self.map.contains_key(string) {
+            let len = self.map.len() as Symbol;
+            self.vec.push(string.to_owned());
+            self.map.insert(string.to_owned(), len);
+            len
+        }
+        else {
+            *self.map.get(string).unwrap()
+        }
+    }
+    pub fn concat(&mut self, id1: Symbol, id2: Symbol) -> Symbol {
+        let string = self.vec[id1 as usize].to_owned() + &self.vec[id2 as usize];
+        self.intern(&string)
+    }
+    /// The symbol for `string`, if it has been interned.
+    pub fn lookup(&self, string: &str) -> Option<Symbol> {
+        self.map.get(string).cloned()
+    }
+    /// The string interned as `symbol`.
+    pub fn decode(&self, symbol: Symbol) -> &str {
+        &self.vec[symbol as usize]
+    }
+    /// The number of interned strings, whose symbols are those below it.
+    pub fn len(&self) -> usize { self.vec.len() }
+    /// Whether no strings have been interned.
+    pub fn is_empty(&self) -> bool { self.vec.is_empty() }
+}
@@ -0,0 +1,676 @@
+//! OWL2-EL ontologies, encoded as the six relations `galen` reads.
+//!
+//! Axioms are normalized as in Liagouris et al., after Baader, Brandt, and Lutz, into
+//!
+//! ```text
+//! p(A,B)      A ⊑ B               s(r,t)      r ⊑ t
+//! q(A,r,B)    A ⊑ ∃r.B            r(r,t,v)    r ∘ t ⊑ v
+//! c(A,B,C)    A ⊓ B ⊑ C           u(A,r,B)    ∃r.A ⊑ B
+//! ```
+//!
+//! where nested class expressions and longer role chains are named by fresh symbols `_:n`.
+//! Every concept `A` also gets `p(A,A)` and `p(A,⊤)`, as the rules assume both. Bottom is an
+//! ordinary concept, so unsatisfiable concepts are those subsumed by `owl:Nothing`.
+//!
+//! Concepts and roles share one symbol table, written as `symbols.txt` alongside the relations.
+//! Axioms outside EL, or outside what `galen` evaluates (unions, inverses, ranges, individuals),
+//! are skipped and counted by construct.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::path::Path;
+
+use crate::{StringInterner, Symbol};
+use crate::generate::write;
+
+/// The IRI of `owl:Thing`, or ⊤.
+pub const THING: &str = "http://www.w3.org/2002/07/owl#Thing";
+/// The IRI of `owl:Nothing`, or ⊥.
+pub const NOTHING: &str = "http://www.w3.org/2002/07/owl#Nothing";
+
+/// A class expression in the EL fragment.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Concept {
+    Named(Symbol),
+    And(Vec<Concept>),
+    Some(Symbol, Box<Concept>),
+}
+
+/// An ontology being encoded.
+pub struct Encoding {
+    symbols: StringInterner,
+    /// Symbols used as concepts, which get reflexive and top subsumptions.
+    concepts: BTreeSet<Symbol>,
+    c: BTreeSet<(Symbol, Symbol, Symbol)>,
+    p: BTreeSet<(Symbol, Symbol)>,
+    q: BTreeSet<(Symbol, Symbol, Symbol)>,
+    r: BTreeSet<(Symbol, Symbol, Symbol)>,
+    s: BTreeSet<(Symbol, Symbol)>,
+    u: BTreeSet<(Symbol, Symbol, Symbol)>,
+    /// Atoms `X` with `C ⊑ X`, and with `X ⊑ C`, for the complex expressions named so far.
+    lower: HashMap<Concept, Symbol>,
+    upper: HashMap<Concept, Symbol>,
+    fresh: usize,
+    prefixes: HashMap<String, String>,
+    skipped: BTreeMap<String, usize>,
+    thing: Symbol,
+    nothing: Symbol,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoding {
+
+    /// Creates an encoding of the empty ontology, with only ⊤ and ⊥.
+    pub fn new() -> Self {
+        let mut symbols = StringInterner::new();
+        let thing = symbols.intern(THING);
+        let nothing = symbols.intern(NOTHING);
+        let mut prefixes = HashMap::new();
+        prefixes.insert("owl".to_owned(), "http://www.w3.org/2002/07/owl#".to_owned());
+        Encoding {
+            symbols,
+            concepts: vec![thing, nothing].into_iter().collect(),
+            c: BTreeSet::new(),
+            p: BTreeSet::new(),
+            q: BTreeSet::new(),
+            r: BTreeSet::new(),
+            s: BTreeSet::new(),
+            u: BTreeSet::new(),
+            lower: HashMap::new(),
+            upper: HashMap::new(),
+            fresh: 0,
+            prefixes,
+            skipped: BTreeMap::new(),
+            thing,
+            nothing,
+        }
+    }
+
+    /// Constructs skipped so far, with the number of axioms or tags each cost.
+    pub fn skipped(&self) -> &BTreeMap<String, usize> { &self.skipped }
+
+    /// Reads an ontology in OWL2 functional syntax.
+    pub fn read_functional(&mut self, text: &str) -> Result<(), String> {
+        for expr in parse(&tokenize(text)?)? {
+            match expr {
+                Expr::List(ref head, ref args) if head == "Prefix" => {
+                    match (args.first(), args.get(1)) {
+                        (Some(Expr::Leaf(name)), Some(Expr::Leaf(iri))) if iri.starts_with('<') => {
+                            let name = name.trim_end_matches('=').trim_end_matches(':');
+                            self.prefixes.insert(name.to_owned(), iri[1 .. iri.len() - 1].to_owned());
+                        },
+                        _ => return Err("malformed Prefix".to_owned()),
+                    }
+                },
+                Expr::List(ref head, ref args) if head == "Ontology" => {
+                    for arg in args.iter() {
+                        if let Expr::List(head, args) = arg {
+                            let args = args.iter().filter(|arg| !arg.is("Annotation")).collect::<Vec<_>>();
+                            if let Err(construct) = self.axiom(head, &args) {
+                                *self.skipped.entry(construct).or_insert(0) += 1;
+                            }
+                        }
+                    }
+                },
+                Expr::List(head, _) => return Err(format!("expected Prefix or Ontology, found {}", head)),
+                Expr::Leaf(leaf) => return Err(format!("expected Prefix or Ontology, found {}", leaf)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes one functional-syntax axiom, or names the construct that prevents it.
+    fn axiom(&mut self, head: &str, args: &[&Expr]) -> Result<(), String> {
+        match head {
+            "Declaration" | "AnnotationAssertion" | "SubAnnotationPropertyOf" |
+            "AnnotationPropertyDomain" | "AnnotationPropertyRange" | "Import" | "Annotation" => { },
+            "SubClassOf" if args.len() == 2 => {
+                let (sub, sup) = (self.concept(args[0])?, self.concept(args[1])?);
+                self.subsume(&sub, &sup);
+            },
+            "EquivalentClasses" | "DisjointClasses" if args.len() >= 2 => {
+                let concepts = args.iter().map(|arg| self.concept(arg)).collect::<Result<Vec<_>, _>>()?;
+                if head == "EquivalentClasses" {
+                    for other in concepts[1..].iter() {
+                        self.subsume(&concepts[0], other);
+                        self.subsume(other, &concepts[0]);
+                    }
+                }
+                else {
+                    let nothing = Concept::Named(self.nothing);
+                    for (index, one) in concepts.iter().enumerate() {
+                        for other in concepts[index + 1 ..].iter() {
+                            self.subsume(&Concept::And(vec![one.clone(), other.clone()]), &nothing);
+                        }
+                    }
+                }
+            },
+            "SubObjectPropertyOf" if args.len() == 2 => {
+                let sup = self.role(args[1])?;
+                let chain = match args[0] {
+                    Expr::List(head, roles) if head == "ObjectPropertyChain" && !roles.is_empty() => {
+                        roles.iter().map(|role| self.role(role)).collect::<Result<Vec<_>, _>>()?
+                    },
+                    role => vec![self.role(role)?],
+                };
+                self.chain(&chain, sup);
+            },
+            "EquivalentObjectProperties" if args.len() >= 2 => {
+                let roles = args.iter().map(|arg| self.role(arg)).collect::<Result<Vec<_>, _>>()?;
+                for &other in roles[1..].iter() {
+                    self.s.insert((roles[0], other));
+                    self.s.insert((other, roles[0]));
+                }
+            },
+            "TransitiveObjectProperty" if args.len() == 1 => {
+                let role = self.role(args[0])?;
+                self.r.insert((role, role, role));
+            },
+            "ObjectPropertyDomain" if args.len() == 2 => {
+                let (role, domain) = (self.role(args[0])?, self.concept(args[1])?);
+                let some = Concept::Some(role, Box::new(Concept::Named(self.thing)));
+                self.subsume(&some, &domain);
+            },
+            _ => return Err(head.to_owned()),
+        }
+        Ok(())
+    }
+
+    /// The class expression `expr`, if it is within EL.
+    fn concept(&mut self, expr: &Expr) -> Result<Concept, String> {
+        match expr {
+            Expr::Leaf(leaf) => {
+                let name = self.expand(leaf)?;
+                Ok(Concept::Named(self.named(&name)))
+            },
+            Expr::List(head, args) if head == "ObjectIntersectionOf" && !args.is_empty() => {
+                Ok(Concept::And(args.iter().map(|arg| self.concept(arg)).collect::<Result<_, _>>()?))
+            },
+            Expr::List(head, args) if head == "ObjectSomeValuesFrom" && args.len() == 2 => {
+                Ok(Concept::Some(self.role(&args[0])?, Box::new(self.concept(&args[1])?)))
+            },
+            Expr::List(head, _) => Err(head.clone()),
+        }
+    }
+
+    /// The named role `expr`; inverses are not in EL.
+    fn role(&mut self, expr: &Expr) -> Result<Symbol, String> {
+        match expr {
+            Expr::Leaf(leaf) => {
+                let name = self.expand(leaf)?;
+                Ok(self.symbols.intern(&name))
+            },
+            Expr::List(head, _) => Err(head.clone()),
+        }
+    }
+
+    /// The full IRI of an entity written as `<iri>` or `prefix:name`.
+    ///
+    /// Names with undeclared prefixes, such as OBO identifiers, are kept as written.
+    fn expand(&self, leaf: &str) -> Result<String, String> {
+        if leaf.starts_with('<') && leaf.ends_with('>') {
+            Ok(leaf[1 .. leaf.len() - 1].to_owned())
+        }
+        else if leaf.starts_with('"') {
+            Err("Literal".to_owned())
+        }
+        else {
+            let mut parts = leaf.splitn(2, ':');
+            match (parts.next().and_then(|prefix| self.prefixes.get(prefix)), parts.next()) {
+                (Some(iri), Some(local)) => Ok(format!("{}{}", iri, local)),
+                _ => Ok(leaf.to_owned()),
+            }
+        }
+    }
+
+    /// Reads an ontology in the OBO flat file format.
+    ///
+    /// Terms contribute `is_a`, `relationship`, `intersection_of`, `equivalent_to`, and
+    /// `disjoint_from`; typedefs contribute `is_a`, `is_transitive`, `transitive_over`,
+    /// `holds_over_chain`, `equivalent_to_chain`, and `domain`. Obsolete stanzas are ignored.
+    pub fn read_obo(&mut self, text: &str) -> Result<(), String> {
+        let mut stanza: Option<String> = None;
+        let mut tags = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                self.stanza(stanza.take(), &tags)?;
+                tags.clear();
+                stanza = Some(line.to_owned());
+            }
+            else if let Some(colon) = line.find(':') {
+                // Values may carry trailing modifiers `{...}` and comments `! ...`.
+                let value = line[colon + 1 ..].split(&['{', '!'][..]).next().unwrap().trim();
+                tags.push((line[.. colon].trim().to_owned(), value.to_owned(), number + 1));
+            }
+        }
+        self.stanza(stanza, &tags)
+    }
+
+    /// Encodes one OBO stanza; the header, with no stanza, is ignored.
+    fn stanza(&mut self, stanza: Option<String>, tags: &[(String, String, usize)]) -> Result<(), String> {
+        let stanza = match stanza {
+            Some(stanza) => stanza,
+            None => return Ok(()),
+        };
+        if stanza != "[Term]" && stanza != "[Typedef]" {
+            *self.skipped.entry(stanza).or_insert(0) += 1;
+            return Ok(());
+        }
+        if tags.iter().any(|(tag, value, _)| tag == "is_obsolete" && value == "true") {
+            return Ok(());
+        }
+        let id = match tags.iter().find(|(tag, _, _)| tag == "id") {
+            Some((_, id, _)) => id.clone(),
+            None => return Err(format!("{} without id", stanza)),
+        };
+
+        if stanza == "[Term]" {
+            let term = Concept::Named(self.named(&id));
+            let mut intersection = Vec::new();
+            for (tag, value, line) in tags.iter() {
+                let words = value.split_whitespace().collect::<Vec<_>>();
+                match (tag.as_str(), words.as_slice()) {
+                    ("is_a", &[parent]) => {
+                        let parent = Concept::Named(self.named(parent));
+                        self.subsume(&term, &parent);
+                    },
+                    ("relationship", &[role, filler]) => {
+                        let some = Concept::Some(self.symbols.intern(role), Box::new(Concept::Named(self.named(filler))));
+                        self.subsume(&term, &some);
+                    },
+                    ("intersection_of", &[genus]) => intersection.push(Concept::Named(self.named(genus))),
+                    ("intersection_of", &[role, filler]) => {
+                        intersection.push(Concept::Some(self.symbols.intern(role), Box::new(Concept::Named(self.named(filler)))));
+                    },
+                    ("equivalent_to", &[other]) => {
+                        let other = Concept::Named(self.named(other));
+                        self.subsume(&term, &other);
+                        self.subsume(&other, &term);
+                    },
+                    ("disjoint_from", &[other]) => {
+                        let both = Concept::And(vec![term.clone(), Concept::Named(self.named(other))]);
+                        let nothing = Concept::Named(self.nothing);
+                        self.subsume(&both, &nothing);
+                    },
+                    ("is_a", _) | ("relationship", _) | ("intersection_of", _) | ("equivalent_to", _) | ("disjoint_from", _) => {
+                        return Err(format!("line {}: malformed {}: {}", line, tag, value));
+                    },
+                    ("union_of", _) => *self.skipped.entry("union_of".to_owned()).or_insert(0) += 1,
+                    _ => { },
+                }
+            }
+            if !intersection.is_empty() {
+                let intersection = Concept::And(intersection);
+                self.subsume(&term, &intersection);
+                self.subsume(&intersection, &term);
+            }
+        }
+        else {
+            let role = self.symbols.intern(&id);
+            for (tag, value, line) in tags.iter() {
+                let words = value.split_whitespace().collect::<Vec<_>>();
+                match (tag.as_str(), words.as_slice()) {
+                    ("is_a", &[parent]) => { let parent = self.symbols.intern(parent); self.s.insert((role, parent)); },
+                    ("is_transitive", &["true"]) => { self.r.insert((role, role, role)); },
+                    ("transitive_over", &[other]) => { let other = self.symbols.intern(other); self.r.insert((role, other, role)); },
+                    ("holds_over_chain", &[first, second]) | ("equivalent_to_chain", &[first, second]) => {
+                        let chain = [self.symbols.intern(first), self.symbols.intern(second)];
+                        self.chain(&chain, role);
+                    },
+                    ("domain", &[domain]) => {
+                        let some = Concept::Some(role, Box::new(Concept::Named(self.thing)));
+                        let domain = Concept::Named(self.named(domain));
+                        self.subsume(&some, &domain);
+                    },
+                    ("is_a", _) | ("transitive_over", _) | ("holds_over_chain", _) | ("equivalent_to_chain", _) | ("domain", _) => {
+                        return Err(format!("line {}: malformed {}: {}", line, tag, value));
+                    },
+                    ("range", _) | ("inverse_of", _) | ("union_of", _) => *self.skipped.entry(tag.clone()).or_insert(0) += 1,
+                    ("is_symmetric", &["true"]) | ("is_reflexive", &["true"]) | ("is_functional", &["true"]) => {
+                        *self.skipped.entry(tag.clone()).or_insert(0) += 1;
+                    },
+                    _ => { },
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The symbol for a named concept.
+    fn named(&mut self, name: &str) -> Symbol {
+        let symbol = self.symbols.intern(name);
+        self.concepts.insert(symbol);
+        symbol
+    }
+
+    /// A fresh symbol, recorded as a concept if `concept`.
+    fn fresh(&mut self, concept: bool) -> Symbol {
+        self.fresh += 1;
+        let name = format!("_:{}", self.fresh);
+        if concept { self.named(&name) } else { self.symbols.intern(&name) }
+    }
+
+    /// Records `sub ⊑ sup`.
+    fn subsume(&mut self, sub: &Concept, sup: &Concept) {
+        match *sub {
+            Concept::Named(atom) => self.implies(atom, sup),
+            _ => {
+                let atom = self.upper(sup);
+                self.implied(sub, atom);
+            },
+        }
+    }
+
+    /// Records `atom ⊑ sup`.
+    fn implies(&mut self, atom: Symbol, sup: &Concept) {
+        match sup {
+            Concept::Named(other) => { self.p.insert((atom, *other)); },
+            Concept::And(conjuncts) => {
+                for conjunct in conjuncts.iter() { self.implies(atom, conjunct); }
+            },
+            Concept::Some(role, filler) => {
+                let filler = self.upper(filler);
+                self.q.insert((atom, *role, filler));
+            },
+        }
+    }
+
+    /// Records `sub ⊑ atom`.
+    fn implied(&mut self, sub: &Concept, atom: Symbol) {
+        match sub {
+            Concept::Named(other) => { self.p.insert((*other, atom)); },
+            Concept::And(conjuncts) => {
+                let atoms = conjuncts.iter().map(|conjunct| self.lower(conjunct)).collect::<Vec<_>>();
+                // Longer intersections fold left, through fresh concepts for the prefixes.
+                let mut prefix = atoms[0];
+                for (index, &next) in atoms.iter().enumerate().skip(1) {
+                    let target = if index + 1 == atoms.len() { atom } else { self.fresh(true) };
+                    self.c.insert((prefix, next, target));
+                    prefix = target;
+                }
+                if atoms.len() == 1 { self.p.insert((prefix, atom)); }
+            },
+            Concept::Some(role, filler) => {
+                let filler = self.lower(filler);
+                self.u.insert((filler, *role, atom));
+            },
+        }
+    }
+
+    /// An atom `X` with `concept ⊑ X`, for use where `concept` is a premise.
+    fn lower(&mut self, concept: &Concept) -> Symbol {
+        if let Concept::Named(atom) = *concept { return atom; }
+        if let Some(&atom) = self.lower.get(concept) { return atom; }
+        let atom = self.fresh(true);
+        self.lower.insert(concept.clone(), atom);
+        self.implied(concept, atom);
+        atom
+    }
+
+    /// An atom `X` with `X ⊑ concept`, for use where `concept` is a conclusion.
+    fn upper(&mut self, concept: &Concept) -> Symbol {
+        if let Concept::Named(atom) = *concept { return atom; }
+        if let Some(&atom) = self.upper.get(concept) { return atom; }
+        let atom = self.fresh(true);
+        self.upper.insert(concept.clone(), atom);
+        self.implies(atom, concept);
+        atom
+    }
+
+    /// Records that the composition of `chain` is subsumed by `sup`.
+    fn chain(&mut self, chain: &[Symbol], sup: Symbol) {
+        if chain.len() == 1 {
+            self.s.insert((chain[0], sup));
+        }
+        else {
+            // Longer chains fold left, through fresh roles for the prefixes.
+            let mut prefix = chain[0];
+            for (index, &next) in chain.iter().enumerate().skip(1) {
+                let target = if index + 1 == chain.len() { sup } else { self.fresh(false) };
+                self.r.insert((prefix, next, target));
+                prefix = target;
+            }
+        }
+    }
+
+    /// Writes the six relations and `symbols.txt` to `directory`, returning the number of rows in each.
+    pub fn write(&self, directory: &Path) -> io::Result<Vec<(&'static str, usize)>> {
+        let mut p = self.p.clone();
+        for &concept in self.concepts.iter() {
+            p.insert((concept, concept));
+            p.insert((concept, self.thing));
+        }
+        let pairs = |set: &BTreeSet<(Symbol, Symbol)>| set.iter().map(|(x, y)| format!("{},{}", x, y)).collect::<Vec<_>>();
+        let triples = |set: &BTreeSet<(Symbol, Symbol, Symbol)>| set.iter().map(|(x, y, z)| format!("{},{},{}", x, y, z)).collect::<Vec<_>>();
+        let symbols = (0 .. self.symbols.len() as Symbol).map(|symbol| format!("{}\t{}", symbol, self.symbols.decode(symbol)));
+        Ok(vec![
+            ("c.txt", write(directory, "c.txt", triples(&self.c))?),
+            ("p.txt", write(directory, "p.txt", pairs(&p))?),
+            ("q.txt", write(directory, "q.txt", triples(&self.q))?),
+            ("r.txt", write(directory, "r.txt", triples(&self.r))?),
+            ("s.txt", write(directory, "s.txt", pairs(&self.s))?),
+            ("u.txt", write(directory, "u.txt", triples(&self.u))?),
+            ("symbols.txt", write(directory, "symbols.txt", symbols)?),
+        ])
+    }
+}
+
+/// A functional-syntax expression: a name, IRI, or literal, or a constructor applied to arguments.
+#[derive(Debug)]
+enum Expr {
+    Leaf(String),
+    List(String, Vec<Expr>),
+}
+
+impl Expr {
+    /// Whether this applies the constructor `name`.
+    fn is(&self, name: &str) -> bool {
+        match self {
+            Expr::List(head, _) => head == name,
+            Expr::Leaf(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Text(String),
+}
+
+/// Splits functional syntax into parentheses and words, with line numbers.
+///
+/// IRIs keep their angle brackets and literals their quotes; `#` starts a comment elsewhere.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(next) = chars.next() {
+        match next {
+            '\n' => line += 1,
+            '(' => tokens.push((line, Token::Open)),
+            ')' => tokens.push((line, Token::Close)),
+            '#' => while chars.peek().map_or(false, |&c| c != '\n') { chars.next(); },
+            '<' => {
+                let mut iri = String::from("<");
+                loop {
+                    match chars.next() {
+                        Some('>') => break,
+                        Some(c) => iri.push(c),
+                        None => return Err(format!("line {}: unterminated IRI", line)),
+                    }
+                }
+                iri.push('>');
+                tokens.push((line, Token::Text(iri)));
+            },
+            '"' => {
+                let start = line;
+                let mut literal = String::from("\"");
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => if let Some(c) = chars.next() { literal.push(c); },
+                        Some(c) => { if c == '\n' { line += 1; } literal.push(c); },
+                        None => return Err(format!("line {}: unterminated literal", start)),
+                    }
+                }
+                literal.push('"');
+                tokens.push((start, Token::Text(literal)));
+            },
+            c if c.is_whitespace() => { },
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '<' || c == '"' { break; }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push((line, Token::Text(word)));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+/// Assembles tokens into expressions, each constructor name followed by its parenthesized arguments.
+fn parse(tokens: &[(usize, Token)]) -> Result<Vec<Expr>, String> {
+    let mut stack: Vec<(String, Vec<Expr>)> = vec![(String::new(), Vec::new())];
+    let mut index = 0;
+    while index < tokens.len() {
+        let (line, ref token) = tokens[index];
+        match token {
+            Token::Text(text) if tokens.get(index + 1).map(|t| &t.1) == Some(&Token::Open) => {
+                stack.push((text.clone(), Vec::new()));
+                index += 1;
+            },
+            Token::Text(text) => stack.last_mut().unwrap().1.push(Expr::Leaf(text.clone())),
+            Token::Open => return Err(format!("line {}: parenthesis without constructor", line)),
+            Token::Close => {
+                if stack.len() == 1 { return Err(format!("line {}: unbalanced parenthesis", line)); }
+                let (head, args) = stack.pop().unwrap();
+                stack.last_mut().unwrap().1.push(Expr::List(head, args));
+            },
+        }
+        index += 1;
+    }
+    if stack.len() > 1 { return Err(format!("{} unclosed at end of input", stack.last().unwrap().0)); }
+    Ok(stack.pop().unwrap().1)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// An encoding of `axioms`, with the empty prefix standing for no IRI so that names read as written.
+    fn functional(axioms: &str) -> Encoding {
+        let mut encoding = Encoding::default();
+        encoding.read_functional(&format!("Prefix(:=<>)\nOntology(<http://example.org/test>\n{}\n)", axioms)).unwrap();
+        encoding
+    }
+
+    fn pairs<'a>(encoding: &'a Encoding, set: &BTreeSet<(Symbol, Symbol)>) -> BTreeSet<(&'a str, &'a str)> {
+        set.iter().map(|&(x, y)| (encoding.symbols.decode(x), encoding.symbols.decode(y))).collect()
+    }
+
+    fn triples<'a>(encoding: &'a Encoding, set: &BTreeSet<(Symbol, Symbol, Symbol)>) -> BTreeSet<(&'a str, &'a str, &'a str)> {
+        set.iter().map(|&(x, y, z)| (encoding.symbols.decode(x), encoding.symbols.decode(y), encoding.symbols.decode(z))).collect()
+    }
+
+    #[test]
+    fn functional_prefixes_and_annotations() {
+        let mut encoding = Encoding::default();
+        encoding.read_functional(r#"
+            Prefix(ex:=<http://example.org/>)
+            Prefix(obo:=<http://purl.obolibrary.org/obo/>)
+            Ontology(<http://example.org/heart>
+                Annotation(rdfs:comment "an (unbalanced \"quoted\" comment")
+                Declaration(Class(ex:Heart))
+                # A comment, which ends at the line break.
+                SubClassOf(Annotation(rdfs:comment "mind the )") ex:Heart obo:UBERON_0000062)
+                AnnotationAssertion(rdfs:label ex:Heart "heart")
+            )
+        "#).unwrap();
+        let expected = vec![("http://example.org/Heart", "http://purl.obolibrary.org/obo/UBERON_0000062")];
+        assert_eq!(pairs(&encoding, &encoding.p), expected.into_iter().collect());
+        assert!(encoding.skipped().is_empty());
+    }
+
+    #[test]
+    fn functional_skips_outside_el() {
+        let encoding = functional("SubClassOf(:A ObjectUnionOf(:B :C))\nSubClassOf(:A :B)");
+        assert_eq!(pairs(&encoding, &encoding.p), vec![("A", "B")].into_iter().collect());
+        assert_eq!(encoding.skipped().get("ObjectUnionOf"), Some(&1));
+    }
+
+    #[test]
+    fn nested_premise() {
+        // A ⊓ ∃r.(B ⊓ C) ⊑ D names B ⊓ C as _:2, and ∃r._:2 as _:1.
+        let encoding = functional("SubClassOf(ObjectIntersectionOf(:A ObjectSomeValuesFrom(:r ObjectIntersectionOf(:B :C))) :D)");
+        assert_eq!(triples(&encoding, &encoding.c), vec![("B", "C", "_:2"), ("A", "_:1", "D")].into_iter().collect());
+        assert_eq!(triples(&encoding, &encoding.u), vec![("_:2", "r", "_:1")].into_iter().collect());
+        assert!(encoding.p.is_empty() && encoding.q.is_empty());
+    }
+
+    #[test]
+    fn nested_conclusion() {
+        // D ⊑ ∃r.(B ⊓ ∃s.C) names B ⊓ ∃s.C as _:1.
+        let encoding = functional("SubClassOf(:D ObjectSomeValuesFrom(:r ObjectIntersectionOf(:B ObjectSomeValuesFrom(:s :C))))");
+        assert_eq!(pairs(&encoding, &encoding.p), vec![("_:1", "B")].into_iter().collect());
+        assert_eq!(triples(&encoding, &encoding.q), vec![("D", "r", "_:1"), ("_:1", "s", "C")].into_iter().collect());
+        assert!(encoding.c.is_empty() && encoding.u.is_empty());
+    }
+
+    #[test]
+    fn property_chains() {
+        let encoding = functional("
+            SubObjectPropertyOf(ObjectPropertyChain(:a :b :c) :d)
+            SubObjectPropertyOf(ObjectPropertyChain(:a :b) :e)
+            SubObjectPropertyOf(:a :d)
+            TransitiveObjectProperty(:t)
+        ");
+        let expected = vec![("a", "b", "_:1"), ("_:1", "c", "d"), ("a", "b", "e"), ("t", "t", "t")];
+        assert_eq!(triples(&encoding, &encoding.r), expected.into_iter().collect());
+        assert_eq!(pairs(&encoding, &encoding.s), vec![("a", "d")].into_iter().collect());
+        // Fresh roles are not concepts, and get no reflexive subsumption.
+        let fresh = encoding.symbols.lookup("_:1").unwrap();
+        assert!(!encoding.concepts.contains(&fresh));
+    }
+
+    #[test]
+    fn obo_intersection_and_relationship() {
+        let mut encoding = Encoding::default();
+        encoding.read_obo("
+            format-version: 1.2
+
+            [Term]
+            id: X:1
+            intersection_of: X:2
+            intersection_of: part_of X:3
+            relationship: has_part X:4 {cardinality=\"1\"} ! four
+
+            [Term]
+            id: X:5
+            is_obsolete: true
+            is_a: X:1
+
+            [Typedef]
+            id: part_of
+            is_transitive: true
+        ").unwrap();
+        // X:1 ≡ X:2 ⊓ ∃part_of.X:3, with ∃part_of.X:3 named _:1 where it is a premise.
+        assert_eq!(pairs(&encoding, &encoding.p), vec![("X:1", "X:2")].into_iter().collect());
+        assert_eq!(triples(&encoding, &encoding.q), vec![("X:1", "part_of", "X:3"), ("X:1", "has_part", "X:4")].into_iter().collect());
+        assert_eq!(triples(&encoding, &encoding.c), vec![("X:2", "_:1", "X:1")].into_iter().collect());
+        assert_eq!(triples(&encoding, &encoding.u), vec![("X:3", "part_of", "_:1")].into_iter().collect());
+        assert_eq!(triples(&encoding, &encoding.r), vec![("part_of", "part_of", "part_of")].into_iter().collect());
+        assert!(encoding.symbols.lookup("X:5").is_none());
+    }
+}
@@ -0,0 +1,337 @@
+//! Reports on the resources used by a computation, attributed to the rules that built it.
+//!
+//! Operators are anonymous to timely beyond their construction names (`Arrange`, `Join`), so
+//! programs mark out the rules they build with `Labels::label`. A label applies to every
+//! operator subsequently built in the same scope, and to everything nested within them, up to
+//! the next label in that scope. Operators built before any label are reported by their own name.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use timely::communication::Allocate;
+use timely::worker::Worker;
+use timely::dataflow::Scope;
+use timely::logging::{TimelyEvent, StartStop};
+
+use differential_dataflow::logging::DifferentialEvent;
+
+use crate::bench::seconds;
+
+/// Names for the operators built between labelled points in a dataflow.
+///
+/// Every worker builds the same dataflow and records the same labels, so one set is shared.
+/// A label is recorded against its scope and the identifier timely will give the next operator
+/// or channel the worker builds; those identifiers count across the whole worker, whereas an
+/// operator's address ends in its index among its scope's children. `resolve` relates the two.
+#[derive(Clone, Default)]
+pub struct Labels {
+    labels: Arc<Mutex<BTreeMap<Site, String>>>,
+}
+
+/// A label's scope, and the identifier of what the worker builds next.
+type Site = (Vec<usize>, usize);
+
+impl Labels {
+    /// Creates an empty set of labels.
+    pub fn new() -> Self { Self::default() }
+
+    /// Names the operators built in `scope` from now on, until the next label.
+    ///
+    /// A name used again elsewhere is numbered, as `name#2` and so on, to keep rules apart.
+    pub fn label<S: Scope>(&self, scope: &S, name: &str) {
+        let key = (scope.addr(), scope.peek_identifier());
+        let mut labels = self.labels.lock().expect("labels poisoned");
+        if !labels.contains_key(&key) {
+            let uses = labels.values().filter(|label| label.as_str() == name || label.starts_with(&format!("{}#", name))).count();
+            let label = if uses == 0 { name.to_owned() } else { format!("{}#{}", name, uses + 1) };
+            labels.insert(key, label);
+        }
+    }
+
+    /// The labels at the addresses of the first operators they cover, from the operators timely announced.
+    ///
+    /// A scope's children are announced with identifiers in the order of their indices, as a
+    /// nested scope takes its identifier only once its contents are built and no sibling is
+    /// built meanwhile. So a label starts at the child of its scope with the least identifier
+    /// not less than the one recorded; labels followed by no operator in their scope are dropped.
+    fn resolve(&self, operators: &HashMap<(usize, usize), (Vec<usize>, String)>) -> BTreeMap<Vec<usize>, String> {
+        // Workers build identical dataflows, so any one worker's operators will do.
+        let worker = operators.keys().map(|&(worker, _)| worker).min();
+        let labels = self.labels.lock().expect("labels poisoned");
+        let mut resolved = BTreeMap::new();
+        for ((scope, identifier), name) in labels.iter() {
+            let first =
+            operators
+                .iter()
+                .filter(|&(&(w, id), (addr, _))| Some(w) == worker && id >= *identifier && addr.len() == scope.len() + 1 && addr.starts_with(scope))
+                .min_by_key(|&(&(_, id), _)| id);
+            if let Some((_, (addr, _))) = first {
+                resolved.insert(addr.clone(), name.clone());
+            }
+        }
+        resolved
+    }
+}
+
+/// The name of the innermost label covering the operator at `addr`, if any.
+fn covering(labels: &BTreeMap<Vec<usize>, String>, addr: &[usize]) -> Option<String> {
+    for depth in (1 .. addr.len() + 1).rev() {
+        let (scope, index) = (&addr[.. depth - 1], addr[depth - 1]);
+        let mut start = scope.to_vec();
+        start.push(0);
+        let mut end = scope.to_vec();
+        end.push(index);
+        if let Some((_, name)) = labels.range(start ..= end).rfind(|(label, _)| label.len() == depth) {
+            return Some(name.clone());
+        }
+    }
+    None
+}
+
+/// What timely reports about the operators of each worker, keyed by (worker, identifier).
+#[derive(Default)]
+struct Activity {
+    /// Address and name of each operator.
+    operators: HashMap<(usize, usize), (Vec<usize>, String)>,
+    /// Address of the operator each channel delivers to.
+    channels: HashMap<(usize, usize), Vec<usize>>,
+    /// When each operator's current scheduling began.
+    started: HashMap<(usize, usize), Duration>,
+    /// Total time each operator has been scheduled.
+    elapsed: HashMap<(usize, usize), Duration>,
+    /// Records received over each channel.
+    received: HashMap<(usize, usize), usize>,
+}
+
+/// Operators announced by timely and the work they do, across the workers of a process.
+#[derive(Clone, Default)]
+pub struct Operators {
+    activity: Arc<Mutex<Activity>>,
+}
+
+impl Operators {
+    /// Creates an empty record.
+    pub fn new() -> Self { Self::default() }
+
+    /// Listens to `worker`'s timely events; must precede building dataflows.
+    pub fn register<A: Allocate>(&self, worker: &mut Worker<A>) {
+        let activity = self.activity.clone();
+        worker.log_register().insert::<TimelyEvent,_>("timely", move |_time, data| {
+            let mut activity = activity.lock().expect("activity poisoned");
+            for (elapsed, worker, event) in data.drain(..) {
+                match event {
+                    TimelyEvent::Operates(event) => {
+                        activity.operators.insert((worker, event.id), (event.addr, event.name));
+                    },
+                    TimelyEvent::Channels(event) => {
+                        let mut target = event.scope_addr;
+                        target.push(event.target.0);
+                        activity.channels.insert((worker, event.id), target);
+                    },
+                    TimelyEvent::Schedule(event) => {
+                        match event.start_stop {
+                            StartStop::Start => { activity.started.insert((worker, event.id), elapsed); },
+                            StartStop::Stop => {
+                                if let Some(start) = activity.started.remove(&(worker, event.id)) {
+                                    *activity.elapsed.entry((worker, event.id)).or_insert(Duration::from_secs(0)) += elapsed - start;
+                                }
+                            },
+                        }
+                    },
+                    TimelyEvent::Messages(event) => {
+                        if !event.is_send {
+                            *activity.received.entry((worker, event.channel)).or_insert(0) += event.length;
+                        }
+                    },
+                    _ => { },
+                }
+            }
+        });
+    }
+
+    /// The address and name of `worker`'s operator `id`.
+    fn operator(&self, worker: usize, id: usize) -> (Vec<usize>, String) {
+        let activity = self.activity.lock().expect("activity poisoned");
+        activity.operators.get(&(worker, id)).cloned().unwrap_or_else(|| (vec![id], "unknown".to_owned()))
+    }
+
+    /// Prints the time spent and records received by the operators of each rule, costliest first.
+    ///
+    /// Scopes are scheduled around the operators they contain, so only the time of operators
+    /// without operators of their own counts. Records are those received on operator inputs.
+    pub fn profile(&self, labels: &Labels) {
+
+        let activity = self.activity.lock().expect("activity poisoned");
+        let labels = labels.resolve(&activity.operators);
+
+        // Addresses of scopes, which contain other operators.
+        let mut scopes = HashSet::new();
+        for (addr, _) in activity.operators.values() {
+            for depth in 1 .. addr.len() {
+                scopes.insert(addr[.. depth].to_vec());
+            }
+        }
+
+        // Per rule: elapsed time, records received, and operator addresses.
+        let mut rules: HashMap<String, (Duration, usize, HashSet<Vec<usize>>)> = HashMap::new();
+        let rule = |addr: &[usize], operator: &str| covering(&labels, addr).unwrap_or_else(|| format!("({})", operator));
+
+        for (key, (addr, name)) in activity.operators.iter() {
+            if !scopes.contains(addr) {
+                let entry = rules.entry(rule(addr, name)).or_insert_with(|| (Duration::from_secs(0), 0, HashSet::new()));
+                if let Some(elapsed) = activity.elapsed.get(key) { entry.0 += *elapsed; }
+                entry.2.insert(addr.clone());
+            }
+        }
+        for (&(worker, channel), &records) in activity.received.iter() {
+            if let Some(target) = activity.channels.get(&(worker, channel)) {
+                let name = activity.operators.values().find(|(addr, _)| addr == target).map(|(_, name)| name.clone()).unwrap_or_else(|| "scope".to_owned());
+                let entry = rules.entry(rule(target, &name)).or_insert_with(|| (Duration::from_secs(0), 0, HashSet::new()));
+                entry.1 += records;
+            }
+        }
+
+        let mut rows = rules.into_iter().collect::<Vec<_>>();
+        rows.sort_by(|a, b| (b.1).0.cmp(&(a.1).0).then_with(|| a.0.cmp(&b.0)));
+
+        let total = rows.iter().map(|(_, (elapsed, _, _))| *elapsed).sum::<Duration>();
+        println!("{:<40}{:>14}{:>8}{:>16}{:>10}", "rule", "elapsed", "share", "records", "operators");
+        for (name, (elapsed, records, addrs)) in rows.iter() {
+            let share = if total > Duration::from_secs(0) { 100.0 * seconds(*elapsed) / seconds(total) } else { 0.0 };
+            println!("{:<40}{:>14}{:>7.1}%{:>16}{:>10}", name, format!("{:.3?}", elapsed), share, records, addrs.len());
+        }
+        println!("{:<40}{:>14}", "total", format!("{:.3?}", total));
+    }
+}
+
+/// Size of an arrangement at one worker.
+#[derive(Clone, Copy, Default)]
+struct Trace {
+    records: isize,
+    batches: isize,
+}
+
+/// Accounts for the records and batches held by each arrangement, across the workers of a process.
+#[derive(Clone)]
+pub struct Memory {
+    labels: Labels,
+    operators: Operators,
+    /// Approximate bytes per update held in a batch.
+    bytes: usize,
+    traces: Arc<Mutex<HashMap<(usize, usize), Trace>>>,
+}
+
+impl Memory {
+    /// Creates an empty account, estimating each update held in a batch at `bytes`.
+    ///
+    /// Arrangements are located through `operators`, which must also be registered with each worker.
+    pub fn new(labels: Labels, operators: Operators, bytes: usize) -> Self {
+        Memory {
+            labels,
+            operators,
+            bytes,
+            traces: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Listens to `worker`'s arrangement events; must precede building dataflows.
+    pub fn register<A: Allocate>(&self, worker: &mut Worker<A>) {
+        let traces = self.traces.clone();
+        worker.log_register().insert::<DifferentialEvent,_>("differential/arrange", move |_time, data| {
+            let mut traces = traces.lock().expect("traces poisoned");
+            for (_elapsed, worker, event) in data.drain(..) {
+                match event {
+                    DifferentialEvent::Batch(event) => {
+                        let trace = traces.entry((worker, event.operator)).or_insert_with(Trace::default);
+                        trace.records += event.length as isize;
+                        trace.batches += 1;
+                    },
+                    DifferentialEvent::Merge(event) => {
+                        // Only completed merges change what is held: two batches become one.
+                        if let Some(length) = event.complete {
+                            let trace = traces.entry((worker, event.operator)).or_insert_with(Trace::default);
+                            trace.records += length as isize - (event.length1 + event.length2) as isize;
+                            trace.batches -= 1;
+                        }
+                    },
+                    DifferentialEvent::Drop(event) => {
+                        let trace = traces.entry((worker, event.operator)).or_insert_with(Trace::default);
+                        trace.records -= event.length as isize;
+                        trace.batches -= 1;
+                    },
+                    _ => { },
+                }
+            }
+        });
+    }
+
+    /// Prints each arrangement's size summed across workers, largest first.
+    ///
+    /// Events reach the account as workers flush their logs, so the report may trail the
+    /// computation slightly; byte counts are estimates from the per-update size.
+    pub fn report(&self) {
+
+        let labels = self.labels.resolve(&self.operators.activity.lock().expect("activity poisoned").operators);
+        let traces = self.traces.lock().expect("traces poisoned");
+
+        let mut totals: BTreeMap<Vec<usize>, (String, Trace)> = BTreeMap::new();
+        for (&(worker, id), trace) in traces.iter() {
+            let (addr, name) = self.operators.operator(worker, id);
+            let total = totals.entry(addr).or_insert_with(|| (name, Trace::default()));
+            total.1.records += trace.records;
+            total.1.batches += trace.batches;
+        }
+
+        let mut rows = totals.into_iter().collect::<Vec<_>>();
+        rows.sort_by_key(|(addr, (_, trace))| (-trace.records, addr.clone()));
+
+        println!("{:<40}{:<16}{:<20}{:>12}{:>10}{:>14}", "rule", "operator", "address", "records", "batches", "~bytes");
+        let mut total = Trace::default();
+        for (addr, (name, trace)) in rows.iter() {
+            let rule = covering(&labels, addr).unwrap_or_else(|| "-".to_owned());
+            println!("{:<40}{:<16}{:<20}{:>12}{:>10}{:>14}", rule, name, format!("{:?}", addr), trace.records, trace.batches, trace.records * self.bytes as isize);
+            total.records += trace.records;
+            total.batches += trace.batches;
+        }
+        println!("{:<40}{:<16}{:<20}{:>12}{:>10}{:>14}", "total", "", "", total.records, total.batches, total.records * self.bytes as isize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Operators as timely announces them for a dataflow `[0]` that builds an input, a map,
+    /// an iterative scope holding two operators, and an inspect, with channels in between.
+    /// Identifiers count operators and channels across the worker, and the iterative scope
+    /// takes its identifier after its contents; indices count each scope's children from 1.
+    fn operators(workers: usize) -> HashMap<(usize, usize), (Vec<usize>, String)> {
+        let mut operators = HashMap::new();
+        for worker in 0 .. workers {
+            for &(id, ref addr, name) in &[(0, vec![0,1], "Input"), (2, vec![0,2], "Map"), (4, vec![0,3,1], "Join"), (6, vec![0,3,2], "Distinct"), (7, vec![0,3], "Iterative"), (9, vec![0,4], "Inspect")] {
+                operators.insert((worker, id), (addr.clone(), name.to_owned()));
+            }
+        }
+        operators
+    }
+
+    fn labels(labels: &[(Vec<usize>, usize, &str)]) -> Labels {
+        let result = Labels::new();
+        for (scope, identifier, name) in labels.iter() {
+            result.labels.lock().unwrap().insert((scope.clone(), *identifier), name.to_string());
+        }
+        result
+    }
+
+    #[test]
+    fn resolve_by_identifier() {
+        let labels = labels(&[(vec![0], 0, "inputs"), (vec![0], 1, "rule"), (vec![0,3], 4, "inner"), (vec![0], 8, "after"), (vec![0], 10, "unused")]);
+        let resolved = labels.resolve(&operators(2));
+        let names = [vec![0,1], vec![0,2], vec![0,3], vec![0,3,1], vec![0,3,2], vec![0,4]].iter().map(|addr| covering(&resolved, addr)).collect::<Vec<_>>();
+        let expected = ["inputs", "rule", "rule", "inner", "inner", "after"].iter().map(|name| Some(name.to_string())).collect::<Vec<_>>();
+        assert_eq!(names, expected);
+        assert_eq!(resolved.len(), 4);
+    }
+}
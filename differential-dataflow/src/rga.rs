@@ -0,0 +1,91 @@
+//! A sequential implementation of the list CRDT `crdt` computes, to check its output.
+//!
+//! Following `problems/crdt/query.dl`, each inserted element is a child of the element it was
+//! inserted after, and the document is the pre-order traversal of this tree from the head
+//! `(0, 0)`, visiting siblings in descending order of their ids. Removed elements keep their
+//! place in the tree but are not visible. Elements whose parent has not been inserted are not
+//! reachable from the head, and do not appear.
+
+use std::collections::{HashMap, HashSet};
+
+/// A Lamport timestamp `(ctr, node)` naming an element; `(0, 0)` is the head of the document.
+pub type Id = (usize, usize);
+
+/// The head of every document, which is never visible.
+pub const HEAD: Id = (0, 0);
+
+/// A document, as the inserts and removes applied to it.
+#[derive(Default)]
+pub struct Document {
+    children: HashMap<Id, Vec<Id>>,
+    removed: HashSet<Id>,
+}
+
+impl Document {
+    /// Creates an empty document.
+    pub fn new() -> Self { Self::default() }
+
+    /// Inserts `id` as a child of `parent`, that is, immediately after it when inserted.
+    pub fn insert(&mut self, id: Id, parent: Id) {
+        self.children.entry(parent).or_insert_with(Vec::new).push(id);
+    }
+
+    /// Removes `id`, which may be inserted before or after.
+    pub fn remove(&mut self, id: Id) {
+        self.removed.insert(id);
+    }
+
+    /// The visible elements, in document order.
+    pub fn order(&self) -> Vec<Id> {
+        let mut order = Vec::new();
+        let mut stack = vec![HEAD];
+        while let Some(id) = stack.pop() {
+            if id != HEAD && !self.removed.contains(&id) {
+                order.push(id);
+            }
+            if let Some(children) = self.children.get(&id) {
+                // The stack pops the last pushed first, so push siblings in ascending order.
+                let mut children = children.clone();
+                children.sort();
+                stack.extend(children);
+            }
+        }
+        order
+    }
+
+    /// Pairs of adjacent visible elements, as `crdt` reports them in `result`.
+    pub fn pairs(&self) -> Vec<(Id, Id)> {
+        let order = self.order();
+        order.windows(2).map(|pair| (pair[0], pair[1])).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// The example tree from `problems/crdt/query.dl`, whose list is `[0, 2, 6, 5, 3, 1, 4]`.
+    fn example() -> Document {
+        let mut document = Document::new();
+        for &(id, parent) in &[(1, 0), (2, 0), (3, 2), (4, 1), (5, 2), (6, 2)] {
+            document.insert((id, 0), (parent, 0));
+        }
+        document
+    }
+
+    #[test]
+    fn example_order() {
+        let order = example().order().into_iter().map(|(ctr, _)| ctr).collect::<Vec<_>>();
+        assert_eq!(order, vec![2, 6, 5, 3, 1, 4]);
+    }
+
+    #[test]
+    fn removed_keep_their_place() {
+        let mut document = example();
+        document.remove((5, 0));
+        document.remove((1, 0));
+        let pairs = document.pairs().into_iter().map(|((prev, _), (next, _))| (prev, next)).collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(2, 6), (6, 3), (3, 4)]);
+    }
+}
@@ -0,0 +1,254 @@
+//! A socket server over a running computation.
+//!
+//! The server listens on a localhost TCP address, or a Unix domain socket when the address
+//! is written `unix:<path>`, and serves one connection at a time from worker zero. Each
+//! connection speaks a line-based protocol in which every request is answered by zero or
+//! more lines of output followed by a line `ok` or `error: <reason>`.
+//!
+//! Insertions and retractions are buffered in a transaction until `commit`, which stages
+//! them all in the shell's inputs, advances the epoch, and waits until the probe passes it.
+//! The reply acknowledges the completed timestamp and lists the changes to each output:
+//!
+//! ```text
+//! insert p 1 2
+//! ok
+//! commit
+//! ack 1
+//! +1	p	1	2
+//! +1	p	1	7
+//! ok
+//! ```
+
+use std::io::{BufRead, BufReader, Write, BufWriter};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use timely::communication::Allocate;
+use timely::worker::Worker;
+use timely::dataflow::ProbeHandle;
+
+use crate::shell::{Shell, Symbols, Time, Diff, tokenize};
+
+const HELP: &str = "\
+commands:
+  insert <input> <values..>     add the addition of a fact to the transaction
+  remove <input> <values..>     add the retraction of a fact to the transaction
+  begin | abort                 discard the transaction
+  commit                        apply the transaction and report output changes
+  relations                     list inputs and outputs
+  query <output> <pattern..>    list facts matching a pattern, with `_` as wildcard
+  count [<output>]              count the facts in one or all outputs
+  quit                          close the connection
+  shutdown                      close the connection and stop the server";
+
+/// What to do after a connection closes.
+enum Next {
+    Accept,
+    Shutdown,
+}
+
+/// Serves connections at `address` until a client asks for `shutdown`.
+pub fn serve<S: Symbols, A: Allocate>(shell: &mut Shell<S>, address: &str, worker: &mut Worker<A>, probe: &ProbeHandle<Time>) -> ::std::io::Result<()> {
+
+    // Changes up to the first fixpoint are the initial contents, not news.
+    shell.changes();
+
+    if address.starts_with("unix:") {
+        let path = &address["unix:".len()..];
+        let listener = bind(path)?;
+        println!("listening on {}", address);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let reader = BufReader::new(stream.try_clone()?);
+            if let Next::Shutdown = connection(shell, reader, BufWriter::new(stream), worker, probe) { break; }
+        }
+        ::std::fs::remove_file(path)?;
+    }
+    else {
+        // Requests are not authenticated, so only clients on this machine may connect.
+        let addresses = address.to_socket_addrs()?.collect::<Vec<_>>();
+        if let Some(remote) = addresses.iter().find(|socket| !socket.ip().is_loopback()) {
+            let error = format!("{} resolves to {}, which is not a loopback address", address, remote.ip());
+            return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidInput, error));
+        }
+        let listener = TcpListener::bind(&addresses[..])?;
+        println!("listening on {}", listener.local_addr()?);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let reader = BufReader::new(stream.try_clone()?);
+            if let Next::Shutdown = connection(shell, reader, BufWriter::new(stream), worker, probe) { break; }
+        }
+    }
+
+    Ok(())
+}
+
+/// Listens on a Unix domain socket at `path`, replacing one left by a server that did not shut down cleanly.
+///
+/// A socket that still accepts connections belongs to a live server, and is left alone.
+fn bind(path: &str) -> ::std::io::Result<UnixListener> {
+    if let Ok(metadata) = ::std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() && UnixStream::connect(path).is_err() {
+            ::std::fs::remove_file(path)?;
+        }
+    }
+    UnixListener::bind(path)
+}
+
+/// Serves requests from one connection until it closes, reporting rather than propagating its failures.
+fn connection<S: Symbols, A: Allocate, R: BufRead, W: Write>(shell: &mut Shell<S>, reader: R, writer: W, worker: &mut Worker<A>, probe: &ProbeHandle<Time>) -> Next {
+    match requests(shell, reader, writer, worker, probe) {
+        Ok(next) => next,
+        Err(error) => { println!("connection failed: {}", error); Next::Accept },
+    }
+}
+
+/// Answers the requests on one connection.
+fn requests<S: Symbols, A: Allocate, R: BufRead, W: Write>(shell: &mut Shell<S>, reader: R, mut writer: W, worker: &mut Worker<A>, probe: &ProbeHandle<Time>) -> ::std::io::Result<Next> {
+
+    // Changes buffered until the next `commit`.
+    let mut transaction: Vec<(String, Vec<String>, Diff)> = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        let words = match tokenize(&line) {
+            Ok(words) => words,
+            Err(error) => { writeln!(writer, "error: {}", error)?; writer.flush()?; continue; }
+        };
+
+        let result = match words.first().map(|word| word.as_str()) {
+            None => Ok(()),
+            Some("help") => {
+                writeln!(writer, "{}", HELP)?;
+                for usage in shell.usage() { writeln!(writer, "{}", usage)?; }
+                Ok(())
+            },
+            Some("insert") | Some("remove") if words.len() < 2 => Err("missing input name".to_owned()),
+            Some("insert") | Some("remove") => {
+                let diff = if words[0] == "insert" { 1 } else { -1 };
+                // Validating now reports malformed facts against the line that introduced them,
+                // while symbols are minted only at `commit`, so an aborted transaction leaves none.
+                shell.validate(&words[1], &words[2..]).map(|()| transaction.push((words[1].clone(), words[2..].to_vec(), diff)))
+            },
+            Some("begin") | Some("abort") => { transaction.clear(); Ok(()) },
+            Some("commit") => {
+                for (name, values, diff) in transaction.drain(..) {
+                    let row = shell.encode(&name, &values).expect("fact validated when buffered");
+                    shell.update(&name, &row, diff).expect("input validated when buffered");
+                }
+                shell.commit(worker, probe);
+                writeln!(writer, "ack {}", shell.epoch() - 1)?;
+                for (name, values, diff) in shell.changes() {
+                    writeln!(writer, "{:+}\t{}\t{}", diff, name, values.join("\t"))?;
+                }
+                Ok(())
+            },
+            Some("quit") | Some("exit") => { writeln!(writer, "ok")?; writer.flush()?; return Ok(Next::Accept); },
+            Some("shutdown") => { writeln!(writer, "ok")?; writer.flush()?; return Ok(Next::Shutdown); },
+            Some(_) => shell.execute(&line, worker, probe, &mut writer).map(|_| ()),
+        };
+
+        match result {
+            Ok(()) => writeln!(writer, "ok")?,
+            Err(error) => writeln!(writer, "error: {}", error)?,
+        }
+        writer.flush()?;
+    }
+
+    Ok(Next::Accept)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::Cursor;
+
+    use differential_dataflow::input::Input;
+
+    use crate::Symbol;
+    use crate::shell::Numbers;
+    use super::*;
+
+    /// Replies to `lines` from a shell whose input `edges` holds `(1, 2)` at its first fixpoint,
+    /// and whose output `reversed` holds each edge reversed.
+    fn replies(lines: &str) -> String {
+        let lines = lines.to_owned();
+        timely::execute_directly(move |worker| {
+            let mut probe = ProbeHandle::new();
+            let mut shell = Shell::new(Numbers, 1);
+            let mut edges = worker.dataflow::<Time,_,_>(|scope| {
+                let (input, edges) = scope.new_collection::<(Symbol, Symbol), Diff>();
+                shell.output("reversed", 2, &edges.map(|(x, y)| vec![y, x]), &mut probe);
+                input
+            });
+            edges.insert((1, 2));
+            edges.advance_to(1);
+            edges.flush();
+            while probe.less_than(edges.time()) { worker.step(); }
+            shell.input("edges", edges);
+            shell.changes();
+
+            let mut reply = Vec::new();
+            requests(&mut shell, Cursor::new(lines), &mut reply, worker, &probe).expect("requests failed");
+            String::from_utf8(reply).expect("reply not utf8")
+        })
+    }
+
+    /// A fresh path for a socket in the temporary directory.
+    fn socket(name: &str) -> String {
+        let path = ::std::env::temp_dir().join(format!("{}-{}.sock", name, ::std::process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn bind_replaces_stale_socket() {
+        let path = socket("stale");
+        drop(UnixListener::bind(&path).expect("failed to bind"));
+        assert!(bind(&path).is_ok());
+        ::std::fs::remove_file(&path).expect("failed to remove socket");
+    }
+
+    #[test]
+    fn bind_leaves_live_socket() {
+        let path = socket("live");
+        let _live = UnixListener::bind(&path).expect("failed to bind");
+        assert_eq!(bind(&path).err().map(|error| error.kind()), Some(::std::io::ErrorKind::AddrInUse));
+        ::std::fs::remove_file(&path).expect("failed to remove socket");
+    }
+
+    #[test]
+    fn bind_leaves_other_files() {
+        let path = socket("file");
+        ::std::fs::write(&path, "not a socket").expect("failed to write file");
+        assert!(bind(&path).is_err());
+        assert_eq!(::std::fs::read_to_string(&path).ok(), Some("not a socket".to_owned()));
+        ::std::fs::remove_file(&path).expect("failed to remove file");
+    }
+
+    #[test]
+    fn commit_acknowledges_changes() {
+        let reply = replies("insert edges 3 4\nremove edges 1 2\ncommit\n");
+        assert_eq!(reply, "ok\nok\nack 1\n-1\treversed\t2\t1\n+1\treversed\t4\t3\nok\n");
+    }
+
+    #[test]
+    fn commits_advance_epochs() {
+        let reply = replies("insert edges 3 4\ncommit\ncommit\ncount reversed\n");
+        assert_eq!(reply, "ok\nack 1\n+1\treversed\t4\t3\nok\nack 2\nok\nreversed\t2\nok\n");
+    }
+
+    #[test]
+    fn abort_discards_transaction() {
+        let reply = replies("insert edges 3 4\nabort\ncommit\nquery reversed _ _\n");
+        assert_eq!(reply, "ok\nok\nack 1\nok\n2\t1\n(1 facts)\nok\n");
+    }
+
+    #[test]
+    fn malformed_facts_are_refused() {
+        let reply = replies("insert edges 3\ninsert nodes 3\nremove edges x 4\ninsert edges \"3\nquit\ninsert edges 3 4\n");
+        let expected = "error: edges expects 2 values\nerror: unknown input: nodes\nerror: not a number: x\nerror: unterminated quote\nok\n";
+        assert_eq!(reply, expected);
+    }
+}
@@ -0,0 +1,447 @@
+//! An interactive shell over a running computation.
+//!
+//! The shell takes ownership of a computation's input sessions once it has reached its
+//! first fixpoint, and accumulates the contents of selected output collections at worker
+//! zero. Updates are staged in the inputs until `commit` advances the epoch and steps the
+//! worker until the outputs reflect it; queries and counts then read the accumulated outputs.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use timely::communication::Allocate;
+use timely::worker::Worker;
+use timely::dataflow::{Scope, ProbeHandle};
+use timely::dataflow::operators::Exchange;
+
+use differential_dataflow::{AsCollection, Collection};
+use differential_dataflow::input::InputSession;
+
+use crate::{StringInterner, Symbol};
+
+pub type Time = u32;
+pub type Diff = isize;
+
+/// Translation between the text a user types and the symbols held in collections.
+pub trait Symbols {
+    /// The symbol for `text`, minting a new one if needed.
+    fn encode(&mut self, text: &str) -> Result<Symbol, String>;
+    /// Whether `text` could be encoded, without minting a symbol for it.
+    fn check(&self, text: &str) -> Result<(), String>;
+    /// The symbol for `text`, if one exists.
+    fn lookup(&self, text: &str) -> Option<Symbol>;
+    /// The text for `symbol`.
+    fn decode(&self, symbol: Symbol) -> String;
+}
+
+/// Symbols that are just numbers, as in galen.
+pub struct Numbers;
+
+impl Symbols for Numbers {
+    fn encode(&mut self, text: &str) -> Result<Symbol, String> {
+        text.parse().map_err(|_| format!("not a number: {}", text))
+    }
+    fn check(&self, text: &str) -> Result<(), String> {
+        text.parse::<Symbol>().map(|_| ()).map_err(|_| format!("not a number: {}", text))
+    }
+    fn lookup(&self, text: &str) -> Option<Symbol> {
+        text.parse().ok()
+    }
+    fn decode(&self, symbol: Symbol) -> String {
+        symbol.to_string()
+    }
+}
+
+impl Symbols for Rc<RefCell<StringInterner>> {
+    fn encode(&mut self, text: &str) -> Result<Symbol, String> {
+        Ok(self.borrow_mut().intern(text))
+    }
+    fn check(&self, _text: &str) -> Result<(), String> {
+        Ok(())
+    }
+    fn lookup(&self, text: &str) -> Option<Symbol> {
+        self.borrow().lookup(text)
+    }
+    fn decode(&self, symbol: Symbol) -> String {
+        self.borrow().decode(symbol).to_owned()
+    }
+}
+
+/// An input session whose records are tuples of symbols.
+pub trait Input {
+    /// Number of symbols in each record.
+    fn arity(&self) -> usize;
+    /// Stages a change to the record `row`.
+    fn update(&mut self, row: &[Symbol], diff: Diff);
+    /// Advances the session and flushes staged changes.
+    fn advance_to(&mut self, time: Time);
+}
+
+impl Input for InputSession<Time, Symbol, Diff> {
+    fn arity(&self) -> usize { 1 }
+    fn update(&mut self, row: &[Symbol], diff: Diff) { InputSession::update(self, row[0], diff); }
+    fn advance_to(&mut self, time: Time) { InputSession::advance_to(self, time); self.flush(); }
+}
+
+macro_rules! tuple_input {
+    (@symbol $index:tt) => { Symbol };
+    ($arity:expr; $($index:tt),+) => {
+        impl Input for InputSession<Time, ($(tuple_input!(@symbol $index),)+), Diff> {
+            fn arity(&self) -> usize { $arity }
+            fn update(&mut self, row: &[Symbol], diff: Diff) { InputSession::update(self, ($(row[$index],)+), diff); }
+            fn advance_to(&mut self, time: Time) { InputSession::advance_to(self, time); self.flush(); }
+        }
+    };
+}
+
+tuple_input!(2; 0, 1);
+tuple_input!(3; 0, 1, 2);
+tuple_input!(4; 0, 1, 2, 3);
+tuple_input!(5; 0, 1, 2, 3, 4);
+tuple_input!(6; 0, 1, 2, 3, 4, 5);
+tuple_input!(7; 0, 1, 2, 3, 4, 5, 6);
+
+/// Accumulated contents of an output collection, and changes not yet reported.
+struct Output {
+    arity: usize,
+    rows: Rc<RefCell<HashMap<Vec<Symbol>, Diff>>>,
+    changes: Rc<RefCell<Vec<(Vec<Symbol>, Diff)>>>,
+}
+
+const HELP: &str = "\
+commands:
+  relations                     list inputs and outputs
+  insert <input> <values..>     stage the addition of a fact
+  remove <input> <values..>     stage the retraction of a fact
+  commit                        advance to the next epoch and run to completion
+  query <output> <pattern..>    list facts matching a pattern, with `_` as wildcard
+  count [<output>]              count the facts in one or all outputs
+  quit                          stop the shell";
+
+/// A command a program adds to the shell, called with the words after its name.
+pub type Command = Box<dyn FnMut(&[String], &mut dyn Write)->Result<(), String>>;
+
+/// An interactive shell over the inputs and outputs of a dataflow.
+pub struct Shell<S: Symbols> {
+    symbols: S,
+    inputs: BTreeMap<String, Box<dyn Input>>,
+    outputs: BTreeMap<String, Output>,
+    /// Commands added by the program, with their help lines.
+    commands: BTreeMap<String, (String, Command)>,
+    epoch: Time,
+}
+
+impl<S: Symbols> Shell<S> {
+
+    /// Creates a shell whose inputs will be at `epoch` when handed over.
+    pub fn new(symbols: S, epoch: Time) -> Self {
+        Shell {
+            symbols,
+            inputs: BTreeMap::new(),
+            outputs: BTreeMap::new(),
+            commands: BTreeMap::new(),
+            epoch,
+        }
+    }
+
+    /// Hands an input session to the shell under `name`.
+    pub fn input<I: Input+'static>(&mut self, name: &str, input: I) {
+        self.inputs.insert(name.to_owned(), Box::new(input));
+    }
+
+    /// Accumulates `collection` at worker zero under `name`, for queries and counts.
+    pub fn output<G: Scope<Timestamp=Time>>(&mut self, name: &str, arity: usize, collection: &Collection<G, Vec<Symbol>, Diff>, probe: &mut ProbeHandle<Time>) {
+        let rows = Rc::new(RefCell::new(HashMap::new()));
+        let rows2 = rows.clone();
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let changes2 = changes.clone();
+        collection
+            .inner
+            .exchange(|_| 0)
+            .as_collection()
+            .inspect(move |(row, _time, diff)| {
+                let mut rows = rows2.borrow_mut();
+                let remove = {
+                    let count = rows.entry(row.clone()).or_insert(0);
+                    *count += *diff;
+                    *count == 0
+                };
+                if remove { rows.remove(row); }
+                changes2.borrow_mut().push((row.clone(), *diff));
+            })
+            .probe_with(probe);
+        self.outputs.insert(name.to_owned(), Output { arity, rows, changes });
+    }
+
+    /// Adds a command named by the first word of `usage`, and described by `summary` in `help`.
+    pub fn command<F>(&mut self, usage: &str, summary: &str, command: F)
+    where F: FnMut(&[String], &mut dyn Write)->Result<(), String>+'static {
+        let name = usage.split_whitespace().next().expect("command without name");
+        self.commands.insert(name.to_owned(), (format!("  {:<30}{}", usage, summary), Box::new(command)));
+    }
+
+    /// Help lines for the commands added by the program.
+    pub fn usage(&self) -> Vec<String> {
+        self.commands.values().map(|(usage, _)| usage.clone()).collect()
+    }
+
+    /// Reads commands from stdin until it closes or `quit` is entered.
+    pub fn run<A: Allocate>(&mut self, worker: &mut Worker<A>, probe: &ProbeHandle<Time>) {
+        // Changes up to the first fixpoint are the initial contents, not news.
+        self.changes();
+        let stdin = ::std::io::stdin();
+        let stdout = ::std::io::stdout();
+        print!("> ");
+        stdout.lock().flush().expect("failed to flush stdout");
+        for line in stdin.lock().lines() {
+            let line = line.expect("failed to read stdin");
+            match self.execute(&line, worker, probe, &mut stdout.lock()) {
+                Ok(true) => { },
+                Ok(false) => break,
+                Err(error) => println!("error: {}", error),
+            }
+            print!("> ");
+            stdout.lock().flush().expect("failed to flush stdout");
+        }
+    }
+
+    /// Executes one command, writing any response to `out`.
+    ///
+    /// Returns `Ok(false)` when the command asks the shell to stop.
+    pub fn execute<A: Allocate>(&mut self, line: &str, worker: &mut Worker<A>, probe: &ProbeHandle<Time>, out: &mut dyn Write) -> Result<bool, String> {
+
+        let words = tokenize(line)?;
+        let command = match words.first() {
+            Some(command) => command.as_str(),
+            None => return Ok(true),
+        };
+
+        match command {
+            "help" => {
+                writeln!(out, "{}", HELP).map_err(|e| e.to_string())?;
+                for usage in self.usage() {
+                    writeln!(out, "{}", usage).map_err(|e| e.to_string())?;
+                }
+                writeln!(out, "values containing spaces may be written in double quotes.").map_err(|e| e.to_string())?;
+            },
+            "relations" => {
+                for (name, input) in self.inputs.iter() {
+                    writeln!(out, "input\t{}/{}", name, input.arity()).map_err(|e| e.to_string())?;
+                }
+                for (name, output) in self.outputs.iter() {
+                    writeln!(out, "output\t{}/{}", name, output.arity).map_err(|e| e.to_string())?;
+                }
+            },
+            "insert" | "remove" => {
+                let name = words.get(1).ok_or("missing input name")?;
+                let diff = if command == "insert" { 1 } else { -1 };
+                let row = self.encode(name, &words[2..])?;
+                self.update(name, &row, diff)?;
+            },
+            "commit" => {
+                let timer = ::std::time::Instant::now();
+                self.commit(worker, probe);
+                let changes = self.changes().len();
+                writeln!(out, "{:?}\tepoch {} complete, {} output changes", timer.elapsed(), self.epoch - 1, changes).map_err(|e| e.to_string())?;
+            },
+            "query" => {
+                let name = words.get(1).ok_or("missing output name")?;
+                let output = self.outputs.get(name).ok_or_else(|| format!("unknown output: {}", name))?;
+                if words.len() - 2 != output.arity {
+                    return Err(format!("{} expects {} pattern terms", name, output.arity));
+                }
+                // `None` is a wildcard; a word that is not yet a symbol cannot match anything.
+                let pattern = words[2..].iter().map(|word| {
+                    if word == "_" { Some(None) }
+                    else { self.symbols.lookup(word).map(Some) }
+                }).collect::<Option<Vec<_>>>();
+                let rows = output.rows.borrow();
+                let mut matches =
+                rows.iter()
+                    .filter(|&(_, &count)| count > 0)
+                    .filter(|&(row, _)| pattern.as_ref().map(|pattern| row.iter().zip(pattern.iter()).all(|(s, p)| p.map(|p| p == *s).unwrap_or(true))).unwrap_or(false))
+                    .map(|(row, _)| row.iter().map(|s| self.symbols.decode(*s)).collect::<Vec<_>>().join("\t"))
+                    .collect::<Vec<_>>();
+                matches.sort();
+                for row in matches.iter() {
+                    writeln!(out, "{}", row).map_err(|e| e.to_string())?;
+                }
+                writeln!(out, "({} facts)", matches.len()).map_err(|e| e.to_string())?;
+            },
+            "count" => {
+                let names = match words.get(1) {
+                    Some(name) => vec![name.clone()],
+                    None => self.outputs.keys().cloned().collect(),
+                };
+                for name in names.iter() {
+                    let output = self.outputs.get(name).ok_or_else(|| format!("unknown output: {}", name))?;
+                    let count = output.rows.borrow().values().filter(|&&count| count > 0).count();
+                    writeln!(out, "{}\t{}", name, count).map_err(|e| e.to_string())?;
+                }
+            },
+            "quit" | "exit" => {
+                return Ok(false);
+            },
+            _ => {
+                match self.commands.get_mut(command) {
+                    Some((_, added)) => added(&words[1..], out)?,
+                    None => return Err(format!("unknown command: {} (try `help`)", command)),
+                }
+            },
+        }
+
+        Ok(true)
+    }
+
+    /// Encodes `values` as a record for the input `name`, checking its arity.
+    pub fn encode(&mut self, name: &str, values: &[String]) -> Result<Vec<Symbol>, String> {
+        self.validate(name, values)?;
+        values.iter().map(|value| self.symbols.encode(value)).collect()
+    }
+
+    /// Checks that `encode` would accept `values` for the input `name`, without minting symbols.
+    pub fn validate(&self, name: &str, values: &[String]) -> Result<(), String> {
+        let arity = self.inputs.get(name).ok_or_else(|| format!("unknown input: {}", name))?.arity();
+        if values.len() != arity {
+            return Err(format!("{} expects {} values", name, arity));
+        }
+        values.iter().try_for_each(|value| self.symbols.check(value))
+    }
+
+    /// Stages a change to the record `row` of the input `name`.
+    pub fn update(&mut self, name: &str, row: &[Symbol], diff: Diff) -> Result<(), String> {
+        let input = self.inputs.get_mut(name).ok_or_else(|| format!("unknown input: {}", name))?;
+        input.update(row, diff);
+        Ok(())
+    }
+
+    /// The epoch at which staged changes will be committed.
+    pub fn epoch(&self) -> Time { self.epoch }
+
+    /// Drains the changes to each output since the last call, with cancelling changes removed.
+    pub fn changes(&mut self) -> Vec<(String, Vec<String>, Diff)> {
+        let mut result = Vec::new();
+        for (name, output) in self.outputs.iter() {
+            let mut changes = HashMap::new();
+            for (row, diff) in output.changes.borrow_mut().drain(..) {
+                *changes.entry(row).or_insert(0) += diff;
+            }
+            let mut changes = changes.into_iter().filter(|&(_, diff)| diff != 0).collect::<Vec<_>>();
+            changes.sort();
+            for (row, diff) in changes {
+                let values = row.iter().map(|s| self.symbols.decode(*s)).collect();
+                result.push((name.clone(), values, diff));
+            }
+        }
+        result
+    }
+
+    /// Writes the accumulated contents of each output to `<name>.csv` in `directory`.
+    pub fn write(&self, directory: &Path) -> ::std::io::Result<()> {
+        ::std::fs::create_dir_all(directory)?;
+        for (name, output) in self.outputs.iter() {
+            let rows = output.rows.borrow();
+            let mut rows = rows.iter().filter(|&(_, &diff)| diff > 0).map(|(row, _)| row).collect::<Vec<_>>();
+            rows.sort();
+            let rows = rows.into_iter().map(|row| row.iter().map(|s| self.symbols.decode(*s)).collect::<Vec<_>>());
+            write_relation(&directory.join(format!("{}.csv", name)), rows)?;
+        }
+        Ok(())
+    }
+
+    /// Advances all inputs to the next epoch and steps the worker until outputs reflect it.
+    pub fn commit<A: Allocate>(&mut self, worker: &mut Worker<A>, probe: &ProbeHandle<Time>) {
+        self.epoch += 1;
+        for input in self.inputs.values_mut() {
+            input.advance_to(self.epoch);
+        }
+        while probe.less_than(&self.epoch) {
+            worker.step();
+        }
+    }
+}
+
+/// Writes `rows` to `path`, one per line with tab-separated fields.
+///
+/// Fields containing a tab, comma, double quote, or line break are enclosed in double quotes,
+/// with any quotes within doubled, so that `compare` reads them back as written.
+pub fn write_relation<I: IntoIterator<Item=Vec<String>>>(path: &Path, rows: I) -> ::std::io::Result<()> {
+    let mut file = ::std::io::BufWriter::new(File::create(path)?);
+    for row in rows {
+        let fields = row.iter().map(|field| {
+            if field.contains(&['\t', ',', '"', '\n', '\r'][..]) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            }
+            else {
+                field.clone()
+            }
+        });
+        writeln!(file, "{}", fields.collect::<Vec<_>>().join("\t"))?;
+    }
+    file.flush()
+}
+
+/// Splits a line into words, treating double-quoted text as a single word.
+pub(crate) fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&next) = chars.peek() {
+        if next.is_whitespace() {
+            chars.next();
+        }
+        else if next == '"' {
+            chars.next();
+            let mut word = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => word.push(c),
+                    None => return Err("unterminated quote".to_owned()),
+                }
+            }
+            words.push(word);
+        }
+        else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() { break; }
+                word.push(c);
+                chars.next();
+            }
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn tokenize_words_and_quotes() {
+        let words = tokenize("  insert Method \"<A: void m(int, int)>\"  x\t\"\" ").unwrap();
+        assert_eq!(words, vec!["insert", "Method", "<A: void m(int, int)>", "x", ""]);
+        assert_eq!(tokenize("   ").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn validate_mints_no_symbols() {
+        let interner = Rc::new(RefCell::new(StringInterner::new()));
+        let mut shell = Shell::new(interner.clone(), 1);
+        shell.input("edges", InputSession::<Time, (Symbol, Symbol), Diff>::new());
+        assert_eq!(shell.validate("edges", &["a".to_owned(), "b".to_owned()]), Ok(()));
+        assert_eq!(shell.validate("edges", &["a".to_owned()]), Err("edges expects 2 values".to_owned()));
+        assert!(interner.borrow().is_empty());
+        assert_eq!(shell.encode("edges", &["a".to_owned(), "b".to_owned()]), Ok(vec![0, 1]));
+    }
+
+    #[test]
+    fn tokenize_unterminated_quote() {
+        assert!(tokenize("insert p \"1 2").is_err());
+    }
+}